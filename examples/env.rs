@@ -0,0 +1,31 @@
+//! Flies a few episodes of the headless environment with the autopilot at
+//! every skill level.
+
+use my_bevy_game::course::Difficulty;
+use my_bevy_game::env::Env;
+use my_bevy_game::pilot::{Pilot, Skill};
+
+fn main() {
+    let mut env = Env::new(Difficulty::Normal);
+    for skill in [Skill::Master, Skill::Expert, Skill::Average, Skill::Novice] {
+        let mut pilot = Pilot::new(skill, 0);
+        for seed in 0..3 {
+            let mut observation = env.reset(seed);
+            pilot.clear();
+            let mut total = 0.0;
+            loop {
+                let (next, reward, done) = env.step(pilot.act(observation));
+                observation = next;
+                total += reward;
+                if done || env.tick() > 60 * 60 * 5 {
+                    break;
+                }
+            }
+            println!(
+                "{skill:?} seed {seed}: score {} after {} ticks, reward {total:.1}",
+                env.score(),
+                env.tick()
+            );
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use bevy::{
+    asset::{AssetLoader, AssetPath, LoadContext, LoadedAsset},
+    ecs::system::SystemParam,
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
+};
+
+/// Regions of a texture page described by a libGDX `.pack` file such as
+/// `image/flappy.pack`.
+#[derive(TypeUuid, Debug)]
+#[uuid = "4f6a3c1e-8d2b-4b7e-9a53-2c1f0e6d7b90"]
+pub struct AtlasPack {
+    pub image: Handle<Image>,
+    pub regions: HashMap<String, Rect>,
+}
+
+#[derive(Default)]
+pub struct AtlasPackLoader;
+
+impl AssetLoader for AtlasPackLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let text = std::str::from_utf8(bytes)?;
+            let (page, regions) = parse_pack(text)?;
+            let image_path = load_context
+                .path()
+                .parent()
+                .map(|dir| dir.join(&page))
+                .unwrap_or_else(|| page.clone().into());
+            let image_path = AssetPath::new(image_path, None);
+            let pack = AtlasPack {
+                image: load_context.get_handle(image_path.clone()),
+                regions,
+            };
+            load_context.set_default_asset(LoadedAsset::new(pack).with_dependency(image_path));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["pack"]
+    }
+}
+
+fn parse_pack(text: &str) -> Result<(String, HashMap<String, Rect>), bevy::asset::Error> {
+    let mut lines = text
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty());
+    let page = lines
+        .next()
+        .ok_or_else(|| bevy::asset::Error::msg("empty pack file"))?
+        .trim()
+        .to_string();
+
+    let mut regions = HashMap::default();
+    let mut name: Option<String> = None;
+    let mut xy = Vec2::ZERO;
+    for line in lines {
+        let Some((key, value)) = line.split_once(':') else {
+            name = Some(line.trim().to_string());
+            continue;
+        };
+        let Some(region) = &name else {
+            // Page header, e.g. `format: RGBA8888`.
+            continue;
+        };
+        let pair = || -> Result<Vec2, bevy::asset::Error> {
+            let (a, b) = value
+                .split_once(',')
+                .ok_or_else(|| bevy::asset::Error::msg(format!("bad value in line `{line}`")))?;
+            Ok(Vec2::new(a.trim().parse()?, b.trim().parse()?))
+        };
+        match key.trim() {
+            "xy" => xy = pair()?,
+            "size" => {
+                regions.insert(region.clone(), Rect::from_corners(xy, xy + pair()?));
+            }
+            _ => {}
+        }
+    }
+    Ok((page, regions))
+}
+
+/// Either a whole image or a named region of the shared atlas.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Art {
+    Image(&'static str),
+    Region(&'static str),
+}
+
+pub struct ResolvedArt {
+    pub texture: Handle<Image>,
+    pub rect: Option<Rect>,
+    pub size: Vec2,
+}
+
+#[derive(Resource)]
+pub struct Atlas {
+    pub pack: Handle<AtlasPack>,
+}
+
+#[derive(SystemParam)]
+pub struct ArtLoader<'w> {
+    asset_server: Res<'w, AssetServer>,
+    atlas: Res<'w, Atlas>,
+    packs: Res<'w, Assets<AtlasPack>>,
+    images: Res<'w, Assets<Image>>,
+}
+
+impl<'w> ArtLoader<'w> {
+    pub fn image_size(&self, texture: &Handle<Image>) -> Option<Vec2> {
+        Some(self.images.get(texture)?.size())
+    }
+
+    /// Texture, sub-rectangle and size of `art`, or `None` while it is still loading.
+    pub fn resolve(&self, art: Art) -> Option<ResolvedArt> {
+        match art {
+            Art::Image(path) => {
+                let texture: Handle<Image> = self.asset_server.load(path);
+                let size = self.images.get(&texture)?.size();
+                Some(ResolvedArt {
+                    texture,
+                    rect: None,
+                    size,
+                })
+            }
+            Art::Region(name) => {
+                let pack = self.packs.get(&self.atlas.pack)?;
+                let Some(rect) = pack.regions.get(name) else {
+                    warn!("no region named {name} in the atlas");
+                    return None;
+                };
+                Some(ResolvedArt {
+                    texture: pack.image.clone(),
+                    rect: Some(*rect),
+                    size: rect.size(),
+                })
+            }
+        }
+    }
+}
+
+pub struct AtlasPlugin;
+
+impl Plugin for AtlasPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<AtlasPack>()
+            .init_asset_loader::<AtlasPackLoader>()
+            .add_startup_system(load_atlas.in_base_set(StartupSet::PreStartup));
+    }
+}
+
+fn load_atlas(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(Atlas {
+        pack: asset_server.load("image/flappy.pack"),
+    });
+}
@@ -0,0 +1,223 @@
+use bevy::prelude::*;
+use my_bevy_game::env::{Action, Observation};
+use my_bevy_game::pilot::{Pilot, Skill};
+
+use crate::levels::{CourseReset, SelectedLevel};
+use crate::online::Online;
+use crate::pool::Pool;
+use crate::powerups::ActiveEffects;
+use crate::settings::menu_closed;
+use crate::versus::Versus;
+use crate::{
+    flap_birds, mouse_click_system, Bird, Game, Pipe, Player, ScoreText, DISTANCE_X_BETWEEN_PIPE,
+};
+
+/// Seconds on the title screen before the bird starts showing off by itself.
+const ATTRACT_SECS: f32 = 15.0;
+/// Seconds a demo run lies on the ground before the next one starts.
+const DEMO_RESTART_SECS: f32 = 2.0;
+
+/// Flies the first player's bird. F2 hands the bird over and F3 picks how well
+/// it flies. Left alone on the title screen, it also flies demo runs until
+/// someone touches a key or the mouse.
+#[derive(Resource)]
+pub struct Autopilot {
+    pilot: Pilot,
+    pub on: bool,
+    pub demo: bool,
+    /// Seconds nobody touched anything, or the demo bird has been down.
+    idle: f32,
+}
+
+impl Autopilot {
+    /// Whether the bird is being flown for the player, so nothing it does
+    /// counts as theirs.
+    pub fn flying(&self) -> bool {
+        self.on || self.demo
+    }
+}
+
+impl Default for Autopilot {
+    fn default() -> Self {
+        Autopilot {
+            pilot: Pilot::new(Skill::Expert, rand::random()),
+            on: false,
+            demo: false,
+            idle: 0.0,
+        }
+    }
+}
+
+#[derive(Component)]
+struct AutopilotText;
+
+pub struct AutopilotPlugin;
+
+impl Plugin for AutopilotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Autopilot>()
+            .add_startup_system(spawn_autopilot_text)
+            .add_system(toggle_autopilot)
+            .add_system(attract.before(mouse_click_system).run_if(menu_closed))
+            .add_system(
+                fly.before(flap_birds)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(update_autopilot_text);
+    }
+}
+
+fn toggle_autopilot(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut autopilot: ResMut<Autopilot>,
+    online: Option<Res<Online>>,
+) {
+    // Nobody races against a bot online.
+    if keyboard_input.just_pressed(KeyCode::F2) && online.is_none() {
+        autopilot.on = !autopilot.on;
+        autopilot.pilot.clear();
+    }
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        autopilot.pilot.skill = autopilot.pilot.skill.next();
+    }
+}
+
+/// Starts a demo after a while on the title screen, restarts it whenever the
+/// bird comes down, and ends it on any input.
+fn attract(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    mut autopilot: ResMut<Autopilot>,
+    mut game: ResMut<Game>,
+    mut effects: ResMut<ActiveEffects>,
+    mut course: CourseReset,
+    selected: Res<SelectedLevel>,
+    versus: Res<Versus>,
+    online: Option<Res<Online>>,
+    mut players: Query<(&mut Bird, &mut Transform), With<Player>>,
+    mut score_text: Query<&mut Text, With<ScoreText>>,
+) {
+    let touched = keyboard_input.get_just_pressed().next().is_some()
+        || mouse_button_input.get_just_pressed().next().is_some();
+    let restart = if autopilot.demo {
+        if touched {
+            autopilot.demo = false;
+            true
+        } else if game.state == 3 {
+            autopilot.idle += time.delta_seconds();
+            autopilot.idle >= DEMO_RESTART_SECS
+        } else {
+            false
+        }
+    } else {
+        // Only a plain endless run makes a demo.
+        let title = game.state == 0
+            && !autopilot.on
+            && !versus.on
+            && online.is_none()
+            && selected.0.is_none();
+        if touched || !title {
+            autopilot.idle = 0.0;
+            return;
+        }
+        autopilot.idle += time.delta_seconds();
+        if autopilot.idle < ATTRACT_SECS {
+            return;
+        }
+        info!("nobody is playing, starting a demo");
+        autopilot.demo = true;
+        autopilot.pilot.clear();
+        false
+    };
+    if !restart {
+        return;
+    }
+    autopilot.idle = 0.0;
+    autopilot.pilot.clear();
+    course.reset(&mut game, 1, DISTANCE_X_BETWEEN_PIPE);
+    game.state = 0;
+    *effects = ActiveEffects::default();
+    for (mut bird, mut transform) in &mut players {
+        *bird = Bird::default();
+        *transform = Transform::from_xyz(transform.translation.x, 0.0, 0.0);
+    }
+    for mut text in &mut score_text {
+        text.sections[0].value = "0".to_string();
+    }
+}
+
+/// Decides the first player's flap for this tick. A demo also takes off by
+/// itself; otherwise the player starts the run.
+fn fly(
+    game: Res<Game>,
+    mut autopilot: ResMut<Autopilot>,
+    pipes: Res<Pool<Pipe>>,
+    mut players: Query<(&mut Bird, &Transform, &Player)>,
+) {
+    let take_off = game.state == 0 && autopilot.demo;
+    if !autopilot.flying() || !(game.state == 1 || take_off) {
+        return;
+    }
+    for (mut bird, transform, player) in &mut players {
+        if player.index != 0 || !bird.alive() {
+            continue;
+        }
+        if take_off {
+            bird.flap = true;
+            continue;
+        }
+        let pipes = pipes
+            .live()
+            .map(|slot| (slot.x, slot.item.sim.gap, slot.item.sim.dh));
+        let observation = Observation::new(transform.translation.truncate(), bird.speed, pipes);
+        if autopilot.pilot.act(observation) == Action::Flap {
+            bird.flap = true;
+        }
+    }
+}
+
+fn spawn_autopilot_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 24.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_text_alignment(TextAlignment::Right)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                right: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+        AutopilotText,
+    ));
+}
+
+fn update_autopilot_text(
+    autopilot: Res<Autopilot>,
+    mut text: Query<&mut Text, With<AutopilotText>>,
+) {
+    if !autopilot.is_changed() {
+        return;
+    }
+    let value = if autopilot.demo {
+        "Demo - press any key to play".to_string()
+    } else if autopilot.on {
+        format!("Autopilot: {:?} (F3 skill, F2 off)", autopilot.pilot.skill)
+    } else {
+        String::new()
+    };
+    for mut text in &mut text {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
@@ -0,0 +1,330 @@
+//! Flies many headless runs with the autopilot or a random flapper and prints
+//! how they went, so a change to the physics or the course can be checked
+//! before anyone plays it.
+//!
+//! cargo run --release --bin balance -- [--config FILE] [--seeds FIRST..END]
+//!     [--policy master|expert|average|novice|random[:CHANCE]]
+//!     [--difficulty easy|normal|hard] [--max-ticks N] [--format csv|json]
+//!
+//! The config file is RON with any of the fields of `Config`, for example
+//! `(seeds: (0, 5000), policy: Random(0.08), rules: (opening: 140.0))`.
+//! Flags given after it override it.
+
+use std::collections::BTreeMap;
+use std::fs;
+
+use my_bevy_game::course::Difficulty;
+use my_bevy_game::env::{Action, Env};
+use my_bevy_game::pilot::{Pilot, Skill};
+use my_bevy_game::sim::{Cause, Rules, TICK_SECS};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+enum Policy {
+    Pilot(Skill),
+    /// Flaps on any tick with this chance.
+    Random(f64),
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+struct Config {
+    difficulty: Difficulty,
+    policy: Policy,
+    /// First seed and one past the last.
+    seeds: (u64, u64),
+    /// Runs still going after this many ticks count as survived.
+    max_ticks: u32,
+    rules: Rules,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            difficulty: Difficulty::Normal,
+            policy: Policy::Pilot(Skill::Expert),
+            seeds: (0, 1000),
+            // Five minutes of play.
+            max_ticks: 5 * 60 * 60,
+            rules: Rules::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Csv,
+    Json,
+}
+
+struct Outcome {
+    score: i32,
+    ticks: u32,
+    /// `None` when the bird was still flying at the tick limit.
+    cause: Option<Cause>,
+}
+
+#[derive(Serialize)]
+struct Scores {
+    min: i32,
+    p10: i32,
+    median: i32,
+    p90: i32,
+    max: i32,
+    mean: f32,
+}
+
+#[derive(Serialize)]
+struct Report {
+    runs: usize,
+    difficulty: Difficulty,
+    policy: Policy,
+    rules: Rules,
+    scores: Scores,
+    /// How many runs ended on each score.
+    histogram: BTreeMap<i32, usize>,
+    /// How many runs ended each way, plus the ones that survived.
+    causes: BTreeMap<&'static str, usize>,
+    median_survival_secs: f32,
+}
+
+fn parse_policy(value: &str) -> Result<Policy, String> {
+    let skill = match value {
+        "master" => Skill::Master,
+        "expert" => Skill::Expert,
+        "average" => Skill::Average,
+        "novice" => Skill::Novice,
+        _ => {
+            let chance = match value.strip_prefix("random") {
+                Some("") => 0.1,
+                Some(chance) => chance
+                    .strip_prefix(':')
+                    .and_then(|chance| chance.parse().ok())
+                    .filter(|chance| (0.0..=1.0).contains(chance))
+                    .ok_or_else(|| format!("bad flap chance in {value:?}, expected 0 to 1"))?,
+                None => {
+                    return Err(format!(
+                        "unknown policy {value:?}, expected master, expert, average, novice or random[:CHANCE]"
+                    ))
+                }
+            };
+            return Ok(Policy::Random(chance));
+        }
+    };
+    Ok(Policy::Pilot(skill))
+}
+
+fn parse_seeds(value: &str) -> Result<(u64, u64), String> {
+    let bad = || format!("bad seed range {value:?}, expected FIRST..END");
+    let (first, end) = value.split_once("..").ok_or_else(bad)?;
+    let first = first.parse().map_err(|_| bad())?;
+    let end = end.parse().map_err(|_| bad())?;
+    if end <= first {
+        return Err(format!("seed range {value:?} is empty"));
+    }
+    Ok((first, end))
+}
+
+fn parse_args() -> Result<(Config, Format), String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut config = Config::default();
+    if let Some(index) = args.iter().position(|arg| arg == "--config") {
+        let path = args.get(index + 1).ok_or("--config needs a file")?;
+        let text = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+        config = ron::from_str(&text).map_err(|err| format!("{path}: {err}"))?;
+    }
+    let mut format = Format::Csv;
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(String::as_str)
+                .ok_or_else(|| format!("{flag} needs a value"))
+        };
+        match flag.as_str() {
+            "--config" => {
+                value()?;
+            }
+            "--seeds" => config.seeds = parse_seeds(value()?)?,
+            "--policy" => config.policy = parse_policy(value()?)?,
+            "--difficulty" => config.difficulty = value()?.parse()?,
+            "--max-ticks" => {
+                let value = value()?;
+                config.max_ticks = value
+                    .parse()
+                    .map_err(|_| format!("bad tick limit {value:?}"))?;
+            }
+            "--format" => {
+                format = match value()? {
+                    "csv" => Format::Csv,
+                    "json" => Format::Json,
+                    other => return Err(format!("unknown format {other:?}, expected csv or json")),
+                }
+            }
+            other => return Err(format!("unknown argument {other:?}")),
+        }
+    }
+    if config.seeds.1 <= config.seeds.0 {
+        return Err("the seed range is empty".to_string());
+    }
+    // A chance from the config file has not been checked yet.
+    if let Policy::Random(chance) = config.policy {
+        if !(0.0..=1.0).contains(&chance) {
+            return Err(format!("bad flap chance {chance}, expected 0 to 1"));
+        }
+    }
+    Ok((config, format))
+}
+
+fn fly(config: &Config, seed: u64) -> Outcome {
+    let mut env = Env::with_rules(config.difficulty, config.rules);
+    let mut observation = env.reset(seed);
+    let mut pilot = match config.policy {
+        Policy::Pilot(skill) => Some(Pilot::new(skill, seed).with_rules(config.rules)),
+        Policy::Random(_) => None,
+    };
+    let mut rng = StdRng::seed_from_u64(seed);
+    while !env.done() && env.tick() < config.max_ticks {
+        let action = match (&mut pilot, config.policy) {
+            (Some(pilot), _) => pilot.act(observation),
+            (None, Policy::Random(chance)) if rng.gen_bool(chance) => Action::Flap,
+            (None, _) => Action::NoOp,
+        };
+        observation = env.step(action).0;
+    }
+    Outcome {
+        score: env.score(),
+        ticks: env.tick(),
+        cause: env.race().birds[0].cause,
+    }
+}
+
+/// Flies every seed, spread over all cores.
+fn fly_all(config: &Config) -> Vec<Outcome> {
+    let seeds: Vec<u64> = (config.seeds.0..config.seeds.1).collect();
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = seeds.len().div_ceil(threads);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = seeds
+            .chunks(chunk)
+            .map(|seeds| {
+                scope.spawn(move || {
+                    seeds
+                        .iter()
+                        .map(|&seed| fly(config, seed))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("a run panicked"))
+            .collect()
+    })
+}
+
+/// Value below which `share` of the sorted `values` lie.
+fn percentile<T: Copy>(values: &[T], share: f32) -> T {
+    values[((values.len() - 1) as f32 * share).round() as usize]
+}
+
+fn cause_name(cause: Option<Cause>) -> &'static str {
+    match cause {
+        Some(Cause::UpperPipe) => "upper_pipe",
+        Some(Cause::LowerPipe) => "lower_pipe",
+        Some(Cause::Ground) => "ground",
+        Some(Cause::Ceiling) => "ceiling",
+        None => "survived",
+    }
+}
+
+fn report(config: &Config, outcomes: &[Outcome]) -> Report {
+    let mut scores: Vec<i32> = outcomes.iter().map(|outcome| outcome.score).collect();
+    scores.sort_unstable();
+    let mut ticks: Vec<u32> = outcomes.iter().map(|outcome| outcome.ticks).collect();
+    ticks.sort_unstable();
+    let mut histogram = BTreeMap::new();
+    for score in &scores {
+        *histogram.entry(*score).or_default() += 1;
+    }
+    // Every cause is listed, even the ones nothing died of.
+    let mut causes: BTreeMap<_, _> = [
+        Some(Cause::UpperPipe),
+        Some(Cause::LowerPipe),
+        Some(Cause::Ground),
+        Some(Cause::Ceiling),
+        None,
+    ]
+    .into_iter()
+    .map(|cause| (cause_name(cause), 0))
+    .collect();
+    for outcome in outcomes {
+        *causes.entry(cause_name(outcome.cause)).or_default() += 1;
+    }
+    Report {
+        runs: outcomes.len(),
+        difficulty: config.difficulty,
+        policy: config.policy,
+        rules: config.rules,
+        scores: Scores {
+            min: scores[0],
+            p10: percentile(&scores, 0.1),
+            median: percentile(&scores, 0.5),
+            p90: percentile(&scores, 0.9),
+            max: scores[scores.len() - 1],
+            mean: scores.iter().sum::<i32>() as f32 / scores.len() as f32,
+        },
+        histogram,
+        causes,
+        median_survival_secs: percentile(&ticks, 0.5) as f32 * TICK_SECS,
+    }
+}
+
+/// One `section,key,value` row per number, easy to filter in a spreadsheet.
+fn print_csv(report: &Report) {
+    println!("section,key,value");
+    println!("config,runs,{}", report.runs);
+    println!("config,difficulty,{:?}", report.difficulty);
+    println!("config,policy,\"{:?}\"", report.policy);
+    println!("config,gravity,{}", report.rules.gravity);
+    println!("config,flap_speed,{}", report.rules.flap_speed);
+    println!("config,opening,{}", report.rules.opening);
+    let scores = &report.scores;
+    for (key, value) in [
+        ("min", scores.min),
+        ("p10", scores.p10),
+        ("median", scores.median),
+        ("p90", scores.p90),
+        ("max", scores.max),
+    ] {
+        println!("score,{key},{value}");
+    }
+    println!("score,mean,{:.2}", scores.mean);
+    for (score, count) in &report.histogram {
+        println!("histogram,{score},{count}");
+    }
+    for (cause, count) in &report.causes {
+        println!("cause,{cause},{count}");
+    }
+    println!("survival,median_secs,{:.2}", report.median_survival_secs);
+}
+
+fn main() {
+    let (config, format) = match parse_args() {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(2);
+        }
+    };
+    let outcomes = fly_all(&config);
+    let report = report(&config, &outcomes);
+    match format {
+        Format::Csv => print_csv(&report),
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("reports serialize")
+        ),
+    }
+}
@@ -0,0 +1,178 @@
+//! Leaderboard server for development. It keeps scores in memory and flies
+//! every submitted replay again, turning away scores the replay does not
+//! reach. Each connection is served on its own thread, so a slow client or a
+//! long replay does not hold up the others. Start it and run the game with
+//! `--scoreboard http://ADDR`.
+//!
+//! cargo run --bin scoreboard -- [address]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use my_bevy_game::scoreboard::{ScoreEntry, Submission, DEFAULT_URL};
+
+/// Most scores one request may ask for.
+const MAX_LIMIT: usize = 100;
+/// Largest request body taken, far more than any real replay needs.
+const MAX_BODY: usize = 1 << 20;
+/// How long a client may take to send its request or read the answer.
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long flying a replay again may take before the score is turned away.
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    body: Vec<u8>,
+}
+
+fn read_request(stream: &mut TcpStream) -> Result<Request, String> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|err| err.to_string())?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(format!("bad request line {line:?}"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        reader
+            .read_line(&mut header)
+            .map_err(|err| err.to_string())?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().map_err(|_| "bad content length")?;
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(format!("body of {length} bytes is too large"));
+    }
+    let mut body = vec![0; length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|err| err.to_string())?;
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query.to_string(),
+        body,
+    })
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    if let Err(err) = stream.write_all(response.as_bytes()) {
+        eprintln!("could not answer: {err}");
+    }
+}
+
+fn error(reason: &str) -> String {
+    serde_json::json!({ "error": reason }).to_string()
+}
+
+/// Takes the submission if its replay really scores what it claims.
+fn submit(scores: &Mutex<Vec<ScoreEntry>>, body: &[u8]) -> Result<(), String> {
+    let submission: Submission =
+        serde_json::from_slice(body).map_err(|err| format!("bad submission: {err}"))?;
+    // Flown on a thread of its own so that a replay that takes too long can
+    // be given up on.
+    let (verdict, answer) = mpsc::channel();
+    let flown = submission.replay.clone();
+    thread::spawn(move || verdict.send(flown.verify().map(|_| ())));
+    match answer.recv_timeout(VERIFY_TIMEOUT) {
+        Ok(Ok(())) => {}
+        Ok(Err(divergence)) => return Err(format!("the replay does not hold up at {divergence}")),
+        Err(_) => return Err("the replay took too long to fly again".to_string()),
+    }
+    let replay = &submission.replay;
+    let name: String = submission.name.chars().take(16).collect();
+    println!("{name} scored {} on seed {}", replay.score, replay.seed);
+    let mut scores = scores.lock().unwrap();
+    scores.push(ScoreEntry {
+        name,
+        score: replay.score,
+    });
+    // Stable, so earlier scores stay ahead of later ties.
+    scores.sort_by_key(|entry| std::cmp::Reverse(entry.score));
+    Ok(())
+}
+
+fn top(scores: &[ScoreEntry], query: &str) -> String {
+    let limit = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("limit="))
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(10)
+        .min(MAX_LIMIT);
+    let top = &scores[..limit.min(scores.len())];
+    serde_json::to_string(top).expect("scores serialize")
+}
+
+fn main() {
+    let default_addr = DEFAULT_URL.trim_start_matches("http://");
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| default_addr.to_string());
+    let listener = match TcpListener::bind(&addr) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("could not listen on {addr}: {err}");
+            std::process::exit(1);
+        }
+    };
+    println!("leaderboard listening on http://{addr}");
+    let scores = Arc::new(Mutex::new(Vec::new()));
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let scores = scores.clone();
+        thread::spawn(move || serve(stream, &scores));
+    }
+}
+
+/// Answers one request.
+fn serve(mut stream: TcpStream, scores: &Mutex<Vec<ScoreEntry>>) {
+    let timeouts = stream
+        .set_read_timeout(Some(IO_TIMEOUT))
+        .and_then(|()| stream.set_write_timeout(Some(IO_TIMEOUT)));
+    if let Err(err) = timeouts {
+        eprintln!("could not set timeouts: {err}");
+        return;
+    }
+    let request = match read_request(&mut stream) {
+        Ok(request) => request,
+        Err(err) => {
+            respond(&mut stream, "400 Bad Request", &error(&err));
+            return;
+        }
+    };
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/scores") => match submit(scores, &request.body) {
+            Ok(()) => respond(&mut stream, "201 Created", "{}"),
+            Err(err) => {
+                println!("turned away a score: {err}");
+                respond(&mut stream, "422 Unprocessable Entity", &error(&err));
+            }
+        },
+        ("GET", "/scores") => {
+            let top = top(&scores.lock().unwrap(), &request.query);
+            respond(&mut stream, "200 OK", &top)
+        }
+        _ => respond(&mut stream, "404 Not Found", &error("no such endpoint")),
+    }
+}
@@ -0,0 +1,323 @@
+//! Authoritative race server. Clients send their flaps, the server flies
+//! every bird on one seeded course and sends back where they all are.
+//!
+//! cargo run --bin server -- [address]
+
+use std::collections::{BTreeMap, VecDeque};
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use my_bevy_game::course::Difficulty;
+use my_bevy_game::net::{
+    self, BirdState, ClientMessage, ServerMessage, DEFAULT_ADDR, MAX_PLAYERS, MAX_REWIND, PROTOCOL,
+    TIMEOUT,
+};
+use my_bevy_game::sim::{Race, TICK_SECS};
+
+struct Client {
+    id: u32,
+    addr: SocketAddr,
+    name: String,
+    ready: bool,
+    last_heard: Instant,
+}
+
+/// A race in progress, with enough of its past kept to apply flaps that
+/// arrive late.
+struct Run {
+    race: Race,
+    /// Which bird each player flies.
+    birds: Vec<u32>,
+    /// The race before each of the last ticks, oldest first.
+    history: VecDeque<Race>,
+    /// Birds to flap on each tick.
+    flaps: BTreeMap<u32, Vec<usize>>,
+    /// Birds whose players left. They stay out even when ticks are replayed.
+    gone: Vec<usize>,
+}
+
+impl Run {
+    fn new(players: &[&Client]) -> Self {
+        let mut race = Race::new(rand::random(), Difficulty::Normal);
+        for _ in players {
+            race.add_bird(0.0);
+        }
+        race.start();
+        Run {
+            race,
+            birds: players.iter().map(|client| client.id).collect(),
+            history: VecDeque::new(),
+            flaps: BTreeMap::new(),
+            gone: Vec::new(),
+        }
+    }
+
+    fn bird(&self, id: u32) -> Option<usize> {
+        self.birds.iter().position(|bird| *bird == id)
+    }
+
+    fn step(&mut self) {
+        self.history.push_back(self.race.clone());
+        if self.history.len() > MAX_REWIND as usize {
+            self.history.pop_front();
+        }
+        if let Some(birds) = self.flaps.get(&self.race.tick) {
+            for bird in birds {
+                self.race.flap(*bird);
+            }
+        }
+        for bird in &self.gone {
+            self.race.knock_out(*bird);
+        }
+        self.race.step();
+        let oldest = self.race.tick.saturating_sub(MAX_REWIND);
+        self.flaps = self.flaps.split_off(&oldest);
+    }
+
+    /// Applies a flap on the tick the player saw it, replaying the ticks
+    /// since then. Flaps older than the history are moved up to its start.
+    fn flap(&mut self, bird: usize, tick: u32) {
+        let now = self.race.tick;
+        let Some(oldest) = self.history.front().map(|race| race.tick) else {
+            self.flaps.entry(tick.max(now)).or_default().push(bird);
+            return;
+        };
+        let tick = tick.max(oldest);
+        self.flaps.entry(tick).or_default().push(bird);
+        if tick >= now {
+            return;
+        }
+        let back = (now - tick) as usize;
+        let keep = self.history.len() - back;
+        self.race = self.history[keep].clone();
+        self.history.truncate(keep);
+        while self.race.tick < now {
+            self.step();
+        }
+    }
+}
+
+struct Server {
+    socket: UdpSocket,
+    clients: Vec<Client>,
+    next_id: u32,
+    run: Option<Run>,
+}
+
+impl Server {
+    fn send(&self, addr: SocketAddr, message: &ServerMessage) {
+        if let Err(err) = self.socket.send_to(&net::encode(message), addr) {
+            eprintln!("could not send to {addr}: {err}");
+        }
+    }
+
+    fn broadcast(&self, message: &ServerMessage) {
+        for client in &self.clients {
+            self.send(client.addr, message);
+        }
+    }
+
+    fn receive(&mut self) {
+        let mut buf = [0; 1500];
+        loop {
+            let (len, addr) = match self.socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => return,
+                // Windows reports an earlier send to a closed port here.
+                Err(err) if err.kind() == std::io::ErrorKind::ConnectionReset => continue,
+                Err(err) => {
+                    eprintln!("could not receive: {err}");
+                    return;
+                }
+            };
+            match net::decode(&buf[..len]) {
+                Ok(message) => self.handle(addr, message),
+                Err(err) => eprintln!("ignoring bad message from {addr}: {err}"),
+            }
+        }
+    }
+
+    fn handle(&mut self, addr: SocketAddr, message: ClientMessage) {
+        if let ClientMessage::Join { protocol, name } = message {
+            self.join(addr, protocol, name);
+            return;
+        }
+        let Some(index) = self.clients.iter().position(|client| client.addr == addr) else {
+            return;
+        };
+        self.clients[index].last_heard = Instant::now();
+        let id = self.clients[index].id;
+        match message {
+            ClientMessage::Ready => self.clients[index].ready = true,
+            ClientMessage::Flap { tick } => {
+                if let Some(run) = &mut self.run {
+                    if let Some(bird) = run.bird(id) {
+                        run.flap(bird, tick);
+                    }
+                }
+            }
+            ClientMessage::Leave => self.drop_client(index, "left"),
+            ClientMessage::Ping | ClientMessage::Join { .. } => {}
+        }
+    }
+
+    fn join(&mut self, addr: SocketAddr, protocol: u32, name: String) {
+        if protocol != PROTOCOL {
+            let reason = format!("server speaks protocol {PROTOCOL}, not {protocol}");
+            self.send(addr, &ServerMessage::Refused { reason });
+            return;
+        }
+        // A client that did not hear its welcome asks again.
+        if let Some(client) = self.clients.iter_mut().find(|client| client.addr == addr) {
+            client.last_heard = Instant::now();
+            let id = client.id;
+            self.send(addr, &ServerMessage::Welcome { id });
+            return;
+        }
+        if self.clients.len() >= MAX_PLAYERS {
+            let reason = "server is full".to_string();
+            self.send(addr, &ServerMessage::Refused { reason });
+            return;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        println!("{name} joined from {addr} as {id}");
+        self.clients.push(Client {
+            id,
+            addr,
+            name,
+            ready: false,
+            last_heard: Instant::now(),
+        });
+        self.send(addr, &ServerMessage::Welcome { id });
+    }
+
+    /// Forgets a client. A bird still in the race drops out of it.
+    fn drop_client(&mut self, index: usize, why: &str) {
+        let client = self.clients.remove(index);
+        println!("{} {why}", client.name);
+        if let Some(run) = &mut self.run {
+            if let Some(bird) = run.bird(client.id) {
+                run.gone.push(bird);
+            }
+        }
+        self.broadcast(&ServerMessage::Left { id: client.id });
+    }
+
+    fn drop_silent(&mut self) {
+        while let Some(index) = self
+            .clients
+            .iter()
+            .position(|client| client.last_heard.elapsed() > TIMEOUT)
+        {
+            self.drop_client(index, "timed out");
+        }
+    }
+
+    /// Starts a race once everyone connected is ready.
+    fn start_when_ready(&mut self) {
+        if self.run.is_some()
+            || self.clients.is_empty()
+            || !self.clients.iter().all(|client| client.ready)
+        {
+            return;
+        }
+        let players: Vec<&Client> = self.clients.iter().collect();
+        let run = Run::new(&players);
+        let start = ServerMessage::Start {
+            seed: run.race.seed(),
+            difficulty: run.race.difficulty(),
+            players: players
+                .iter()
+                .map(|client| (client.id, client.name.clone()))
+                .collect(),
+        };
+        println!("race starts with {} players", players.len());
+        for client in &mut self.clients {
+            client.ready = false;
+        }
+        self.run = Some(run);
+        self.broadcast(&start);
+    }
+
+    fn tick(&mut self) {
+        let Some(mut run) = self.run.take() else {
+            return;
+        };
+        run.step();
+        let birds = run
+            .birds
+            .iter()
+            .zip(&run.race.birds)
+            .map(|(id, bird)| BirdState {
+                id: *id,
+                y: bird.y,
+                speed: bird.speed,
+                score: bird.score,
+                down: !bird.alive(),
+            })
+            .collect();
+        let state = ServerMessage::State {
+            tick: run.race.tick,
+            birds,
+        };
+        self.broadcast(&state);
+        if !run.race.over() {
+            self.run = Some(run);
+            return;
+        }
+        let mut scores: Vec<(String, i32)> = run
+            .birds
+            .iter()
+            .zip(&run.race.birds)
+            .map(|(id, bird)| {
+                let name = self
+                    .clients
+                    .iter()
+                    .find(|client| client.id == *id)
+                    .map_or_else(|| format!("player {id}"), |client| client.name.clone());
+                (name, bird.score)
+            })
+            .collect();
+        scores.sort_by_key(|(_, score)| -score);
+        println!("race over: {scores:?}");
+        self.broadcast(&ServerMessage::Results { scores });
+    }
+}
+
+fn main() {
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_ADDR.to_string());
+    let socket = match UdpSocket::bind(&addr) {
+        Ok(socket) => socket,
+        Err(err) => {
+            eprintln!("could not listen on {addr}: {err}");
+            std::process::exit(1);
+        }
+    };
+    socket
+        .set_nonblocking(true)
+        .expect("sockets can be made non-blocking");
+    println!("listening on {addr}");
+
+    let mut server = Server {
+        socket,
+        clients: Vec::new(),
+        next_id: 1,
+        run: None,
+    };
+    let tick = Duration::from_secs_f32(TICK_SECS);
+    let mut next = Instant::now();
+    loop {
+        server.receive();
+        server.drop_silent();
+        server.start_when_ready();
+        server.tick();
+        next += tick;
+        // When running behind, catch up without sleeping.
+        if let Some(wait) = next.checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+    }
+}
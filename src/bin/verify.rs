@@ -0,0 +1,47 @@
+//! Flies recorded runs again on the same deterministic simulation as the game
+//! and says whether each one really scores what it claims, for settling score
+//! disputes and in scripts. A rejected replay is reported with the first tick
+//! on which it parts ways with the simulation. The bird flies with the hitbox
+//! of the skin it was recorded with; runs a shield saved are rejected, as the
+//! simulation has no power-ups.
+//!
+//! cargo run --bin verify -- FILE...
+//!
+//! Exits with 0 when every replay holds up, 1 when any is rejected and 2 when
+//! one cannot be read.
+
+use std::path::Path;
+
+use my_bevy_game::replay::Replay;
+
+fn main() {
+    let files: Vec<String> = std::env::args().skip(1).collect();
+    if files.is_empty() || files.iter().any(|file| file == "--help") {
+        eprintln!("usage: verify FILE...");
+        std::process::exit(2);
+    }
+    let mut code = 0;
+    for file in &files {
+        let replay = match Replay::load(Path::new(file)) {
+            Ok(replay) => replay,
+            Err(err) => {
+                eprintln!("{file}: could not read: {err}");
+                code = 2;
+                continue;
+            }
+        };
+        match replay.verify() {
+            Ok(race) => println!(
+                "{file}: accepted, score {} on seed {}, down on tick {}",
+                replay.score,
+                replay.seed,
+                race.birds[0].down_at.unwrap_or(replay.end)
+            ),
+            Err(divergence) => {
+                println!("{file}: rejected at {divergence}");
+                code = code.max(1);
+            }
+        }
+    }
+    std::process::exit(code);
+}
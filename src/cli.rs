@@ -0,0 +1,371 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bevy::{
+    app::{AppExit, PluginGroupBuilder, ScheduleRunnerPlugin, ScheduleRunnerSettings},
+    prelude::*,
+    render::{settings::WgpuSettings, RenderPlugin},
+    window::{ExitCondition, PresentMode, WindowMode},
+    winit::WinitPlugin,
+};
+use my_bevy_game::course::Difficulty;
+use my_bevy_game::sim::TICK_SECS;
+use my_bevy_game::{replay::Replay, scoreboard, spectate};
+use ron::extensions::Extensions;
+use serde::Deserialize;
+
+use crate::settings::Settings;
+use crate::Game;
+
+pub const USAGE: &str = "\
+usage: my_bevy_game [options]
+
+  --resolution WxH      window size, e.g. 1280x720
+  --fullscreen          borderless fullscreen
+  --vsync on|off        wait for the display between frames
+  --seed N              course seed
+  --difficulty LEVEL    easy, normal or hard
+  --config FILE         RON file with any of these options; flags override it
+  --replay FILE         play back a recorded run
+  --headless            run without a window or renderer
+  --max-frames N        quit after N frames
+  --max-ticks N         quit after N simulation ticks
+  --assets DIR          where the assets are (default assets)
+  --connect ADDR        race online on the server at ADDR
+  --name NAME           name shown to other racers and on the leaderboard
+  --spectate [ADDR]     publish the run to spectators on ADDR
+  --scoreboard [URL]    send scores to the online leaderboard at URL
+  --help                show this and quit";
+
+/// How the game was started, from the command line and an optional config
+/// file. The window options left out come from the saved settings.
+#[derive(Resource, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Options {
+    pub resolution: Option<(f32, f32)>,
+    pub fullscreen: Option<bool>,
+    pub vsync: Option<bool>,
+    pub seed: Option<u64>,
+    pub difficulty: Option<Difficulty>,
+    pub replay: Option<PathBuf>,
+    pub headless: bool,
+    pub max_frames: Option<u32>,
+    pub max_ticks: Option<u32>,
+    pub assets: String,
+    pub connect: Option<String>,
+    pub name: Option<String>,
+    pub spectate: Option<String>,
+    pub scoreboard: Option<String>,
+    /// The run `replay` names, read while parsing so a bad file stops the
+    /// game before a window opens.
+    #[serde(skip)]
+    pub playback: Option<Replay>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            resolution: None,
+            fullscreen: None,
+            vsync: None,
+            seed: None,
+            difficulty: None,
+            replay: None,
+            headless: false,
+            max_frames: None,
+            max_ticks: None,
+            assets: "assets".to_string(),
+            connect: None,
+            name: None,
+            spectate: None,
+            scoreboard: None,
+            playback: None,
+        }
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{flag} expects a whole number, got {value:?}"))
+}
+
+fn parse_resolution(value: &str) -> Result<(f32, f32), String> {
+    let bad = || format!("--resolution expects WIDTHxHEIGHT like 1280x720, got {value:?}");
+    let (width, height) = value.split_once('x').ok_or_else(bad)?;
+    let width: u32 = width.parse().map_err(|_| bad())?;
+    let height: u32 = height.parse().map_err(|_| bad())?;
+    if width == 0 || height == 0 {
+        return Err(bad());
+    }
+    Ok((width as f32, height as f32))
+}
+
+impl Options {
+    /// Parses the arguments after the program name. `Ok(None)` means help
+    /// was asked for.
+    pub fn parse(args: &[String]) -> Result<Option<Options>, String> {
+        let mut options = Options::default();
+        if let Some(index) = args.iter().position(|arg| arg == "--config") {
+            let path = args.get(index + 1).ok_or("--config needs a file")?;
+            let text = std::fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+            // Lets a config write `seed: 5` rather than `seed: Some(5)`.
+            options = ron::Options::default()
+                .with_default_extension(Extensions::IMPLICIT_SOME)
+                .from_str(&text)
+                .map_err(|err| format!("{path}: {err}"))?;
+        }
+        let mut args = args.iter().peekable();
+        while let Some(flag) = args.next() {
+            let flag = flag.as_str();
+            let mut value = || {
+                args.next()
+                    .map(String::as_str)
+                    .ok_or_else(|| format!("{flag} needs a value"))
+            };
+            match flag {
+                "--help" | "-h" => return Ok(None),
+                "--config" => {
+                    value()?;
+                }
+                "--resolution" => options.resolution = Some(parse_resolution(value()?)?),
+                "--fullscreen" => options.fullscreen = Some(true),
+                "--vsync" => {
+                    options.vsync = Some(match value()? {
+                        "on" => true,
+                        "off" => false,
+                        other => return Err(format!("--vsync expects on or off, got {other:?}")),
+                    })
+                }
+                "--seed" => options.seed = Some(parse_number(flag, value()?)?),
+                "--difficulty" => options.difficulty = Some(value()?.parse()?),
+                "--replay" => options.replay = Some(value()?.into()),
+                "--headless" => options.headless = true,
+                "--max-frames" => options.max_frames = Some(parse_number(flag, value()?)?),
+                "--max-ticks" => options.max_ticks = Some(parse_number(flag, value()?)?),
+                "--assets" => options.assets = value()?.to_string(),
+                "--connect" => options.connect = Some(value()?.to_string()),
+                "--name" => options.name = Some(value()?.to_string()),
+                "--spectate" => {
+                    // The address is optional.
+                    let addr = args.next_if(|arg| !arg.starts_with("--"));
+                    options.spectate =
+                        Some(addr.map_or(spectate::DEFAULT_ADDR.to_string(), String::clone));
+                }
+                "--scoreboard" => {
+                    // So is the URL.
+                    let url = args.next_if(|arg| !arg.starts_with("--"));
+                    options.scoreboard =
+                        Some(url.map_or(scoreboard::DEFAULT_URL.to_string(), String::clone));
+                }
+                other => return Err(format!("unknown argument {other:?}")),
+            }
+        }
+        if let Some(path) = &options.replay {
+            let replay =
+                Replay::load(path).map_err(|err| format!("--replay {}: {err}", path.display()))?;
+            options.playback = Some(replay);
+        }
+        if options.playback.is_some() && (options.seed.is_some() || options.difficulty.is_some()) {
+            return Err("--replay flies its own course; leave out --seed and --difficulty".into());
+        }
+        if options.playback.is_some() && options.connect.is_some() {
+            return Err("--replay and --connect do not go together".into());
+        }
+        Ok(Some(options))
+    }
+
+    /// Parses the process arguments, or explains what is wrong with them and
+    /// quits.
+    pub fn from_args() -> Options {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        match Options::parse(&args) {
+            Ok(Some(options)) => options,
+            Ok(None) => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            Err(err) => {
+                eprintln!("error: {err}\n\n{USAGE}");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    /// Bevy's plugins set up for these options. Headless runs have no window
+    /// and no renderer, and are driven by a plain loop instead.
+    pub fn plugins(&self, settings: &Settings) -> PluginGroupBuilder {
+        let plugins = DefaultPlugins.set(AssetPlugin {
+            asset_folder: self.assets.clone(),
+            ..default()
+        });
+        if self.headless {
+            return plugins
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    ..default()
+                })
+                .set(RenderPlugin {
+                    wgpu_settings: WgpuSettings {
+                        backends: None,
+                        ..default()
+                    },
+                })
+                .disable::<WinitPlugin>();
+        }
+        plugins.set(WindowPlugin {
+            primary_window: Some(Window {
+                title: "Flappy Bird".into(),
+                resolution: self.resolution.unwrap_or(settings.resolution()).into(),
+                mode: if self.fullscreen.unwrap_or(settings.fullscreen) {
+                    WindowMode::BorderlessFullscreen
+                } else {
+                    WindowMode::Windowed
+                },
+                present_mode: if self.vsync.unwrap_or(settings.vsync) {
+                    PresentMode::AutoVsync
+                } else {
+                    PresentMode::AutoNoVsync
+                },
+                // Tells wasm to resize the window according to the available canvas
+                fit_canvas_to_parent: true,
+                // Tells wasm not to override default event handling, like F5, Ctrl+R etc.
+                prevent_default_event_handling: false,
+                ..default()
+            }),
+            ..default()
+        })
+    }
+}
+
+/// Makes the options available to every other plugin, so it has to be added
+/// before them, and stops the game at the frame and tick limits.
+pub struct OptionsPlugin(pub Options);
+
+impl Plugin for OptionsPlugin {
+    fn build(&self, app: &mut App) {
+        let options = self.0.clone();
+        if options.headless {
+            app.insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f32(
+                TICK_SECS,
+            )))
+            .add_plugin(ScheduleRunnerPlugin);
+        }
+        if options.max_frames.is_some() {
+            app.add_system(frame_limit.in_base_set(CoreSet::Last));
+        }
+        if options.max_ticks.is_some() {
+            app.add_system(tick_limit.in_schedule(CoreSchedule::FixedUpdate));
+        }
+        app.insert_resource(options);
+    }
+}
+
+fn frame_limit(
+    options: Res<Options>,
+    game: Res<Game>,
+    mut frames: Local<u32>,
+    mut exit: EventWriter<AppExit>,
+) {
+    *frames += 1;
+    if options.max_frames.is_some_and(|max| *frames >= max) {
+        info!("stopping after {} frames, score {}", *frames, game.score);
+        exit.send(AppExit);
+    }
+}
+
+fn tick_limit(
+    options: Res<Options>,
+    game: Res<Game>,
+    mut ticks: Local<u32>,
+    mut exit: EventWriter<AppExit>,
+) {
+    *ticks += 1;
+    if options.max_ticks.is_some_and(|max| *ticks >= max) {
+        info!("stopping after {} ticks, score {}", *ticks, game.score);
+        exit.send(AppExit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<Options>, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Options::parse(&args)
+    }
+
+    /// A file in the temp folder holding `text`, for the options to read.
+    fn temp_file(name: &str, text: &str) -> String {
+        let path = std::env::temp_dir().join(format!("flappy-cli-{}-{name}", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        path.display().to_string()
+    }
+
+    #[test]
+    fn help_stops_parsing() {
+        assert!(parse(&["--seed", "3", "--help"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn resolution_must_be_width_by_height() {
+        let options = parse(&["--resolution", "1280x720"]).unwrap().unwrap();
+        assert_eq!(options.resolution, Some((1280.0, 720.0)));
+        for bad in ["1280", "1280x", "0x720", "wide x tall"] {
+            let err = parse(&["--resolution", bad]).unwrap_err();
+            assert!(err.starts_with("--resolution expects"), "{bad}: {err}");
+        }
+    }
+
+    #[test]
+    fn vsync_is_on_or_off() {
+        let options = parse(&["--vsync", "off"]).unwrap().unwrap();
+        assert_eq!(options.vsync, Some(false));
+        let err = parse(&["--vsync", "maybe"]).unwrap_err();
+        assert_eq!(err, "--vsync expects on or off, got \"maybe\"");
+    }
+
+    #[test]
+    fn a_flag_without_its_value_is_an_error() {
+        assert_eq!(parse(&["--seed"]).unwrap_err(), "--seed needs a value");
+        assert_eq!(
+            parse(&["--headless", "--config"]).unwrap_err(),
+            "--config needs a file"
+        );
+    }
+
+    #[test]
+    fn a_replay_brings_its_own_course() {
+        let replay = Replay {
+            seed: 7,
+            difficulty: Difficulty::Normal,
+            flaps: vec![0],
+            end: 40,
+            score: 0,
+            hitbox: (26.0, 26.0),
+            shielded: None,
+        };
+        let path = temp_file("course.replay.ron", &ron::to_string(&replay).unwrap());
+        let options = parse(&["--replay", &path]).unwrap().unwrap();
+        assert_eq!(options.playback.map(|replay| replay.seed), Some(7));
+        let err = parse(&["--replay", &path, "--seed", "3"]).unwrap_err();
+        assert!(err.starts_with("--replay flies its own course"), "{err}");
+    }
+
+    #[test]
+    fn flags_override_the_config_file() {
+        let path = temp_file(
+            "override.ron",
+            "(seed: 5, vsync: true, resolution: (800.0, 600.0), headless: true)",
+        );
+        let options = parse(&["--seed", "9", "--config", &path, "--vsync", "off"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.seed, Some(9));
+        assert_eq!(options.vsync, Some(false));
+        assert_eq!(options.resolution, Some((800.0, 600.0)));
+        assert!(options.headless);
+    }
+}
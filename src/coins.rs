@@ -0,0 +1,249 @@
+use bevy::{prelude::*, sprite::collide_aabb::collide};
+use rand::Rng;
+
+use crate::atlas::{Art, ArtLoader};
+use crate::autopilot::Autopilot;
+use crate::cli::Options;
+use crate::ghost::Ghost;
+use crate::layout::ViewBounds;
+use crate::pool::{self, Pool, Poolable};
+use crate::profile::Profile;
+use crate::skins::Hitbox;
+use crate::{
+    fit_to_view, is_editing, is_playing, spawn_pipes, AnimationIndices, AnimationTimer, Bird, Pipe,
+    DISTANCE_X_BETWEEN_PIPE, SCROLL_SPEED,
+};
+
+const COIN_FRAMES: [&str; 4] = [
+    "coin_yellow_dark",
+    "coin_yellow_light",
+    "coin_white_light",
+    "coin_yellow_light",
+];
+const COIN_SIZE: f32 = 22.0;
+
+/// Where coins go for each pipe: a column of `in_gap` coins inside the opening
+/// and an arc of `between` coins leading up to it from the previous pipe.
+#[derive(Resource)]
+pub struct CoinPattern {
+    pub in_gap: usize,
+    pub between: usize,
+    /// Chance that a pipe gets any coins at all.
+    pub chance: f64,
+}
+
+impl Default for CoinPattern {
+    fn default() -> Self {
+        CoinPattern {
+            in_gap: 1,
+            between: 3,
+            chance: 0.6,
+        }
+    }
+}
+
+pub struct CoinCollected {
+    pub value: u32,
+}
+
+#[derive(Component)]
+pub struct Coin;
+
+/// The coins that belong to one pipe, kept as children of a single entity so
+/// the pool can move them together.
+pub struct CoinGroup {
+    root: Entity,
+}
+
+impl Poolable for CoinGroup {
+    fn entities(&self) -> Vec<Entity> {
+        vec![self.root]
+    }
+}
+
+#[derive(Resource)]
+struct CoinAtlas(Handle<TextureAtlas>);
+
+pub struct CoinsPlugin;
+
+impl Plugin for CoinsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CoinPattern>()
+            .add_event::<CoinCollected>()
+            .insert_resource(
+                Pool::<CoinGroup>::new(6, DISTANCE_X_BETWEEN_PIPE, DISTANCE_X_BETWEEN_PIPE)
+                    .with_speed(SCROLL_SPEED),
+            )
+            .add_system(
+                pool::scroll::<CoinGroup>
+                    .run_if(is_playing)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(pool::recycle::<CoinGroup>.run_if(is_playing))
+            .add_system(build_coin_atlas)
+            .add_system(
+                fit_coins_to_view
+                    .run_if(resource_changed::<ViewBounds>())
+                    .after(fit_to_view),
+            )
+            .add_system(spawn_coins.after(spawn_pipes).run_if(not(is_editing)))
+            .add_system(collect_coins.run_if(is_playing))
+            .add_system(add_to_wallet.after(collect_coins));
+    }
+}
+
+fn build_coin_atlas(
+    mut commands: Commands,
+    art: ArtLoader,
+    atlas: Option<Res<CoinAtlas>>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    if atlas.is_some() {
+        return;
+    }
+    let Some(frames) = COIN_FRAMES
+        .iter()
+        .map(|name| art.resolve(Art::Region(name)))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return;
+    };
+    let texture = frames[0].texture.clone();
+    let Some(size) = art.image_size(&texture) else {
+        return;
+    };
+    let mut atlas = TextureAtlas::new_empty(texture, size);
+    for frame in frames.iter().filter_map(|frame| frame.rect) {
+        atlas.add_texture(frame);
+    }
+    commands.insert_resource(CoinAtlas(atlases.add(atlas)));
+}
+
+/// Coin groups travel with their pipe, so they share its bounds.
+fn fit_coins_to_view(mut coins: ResMut<Pool<CoinGroup>>, pipes: Res<Pool<Pipe>>) {
+    coins.exit_x = pipes.exit_x;
+    coins.budget = pipes.budget + 1;
+}
+
+fn spawn_coins(
+    mut commands: Commands,
+    atlas: Option<Res<CoinAtlas>>,
+    pattern: Res<CoinPattern>,
+    pipes: Res<Pool<Pipe>>,
+    mut coins: ResMut<Pool<CoinGroup>>,
+    mut visibilities: Query<&mut Visibility>,
+) {
+    let Some(atlas) = atlas else {
+        return;
+    };
+    // Each group sits on a pipe, however far apart levels put them.
+    while let Some(x) = coins.next_after(pipes.live().map(|slot| slot.x)) {
+        let live: Vec<(f32, f32)> = pipes
+            .live()
+            .map(|slot| (slot.x, slot.item.sim.gap))
+            .collect();
+        let Some(index) = live.iter().position(|(pipe_x, _)| (pipe_x - x).abs() < 1.0) else {
+            // The pipe for this spot has not been spawned yet.
+            break;
+        };
+        let pipe = live[index].1;
+        let (before_x, before) = index
+            .checked_sub(1)
+            .map_or((x - DISTANCE_X_BETWEEN_PIPE, pipe), |i| live[i]);
+
+        let group = match coins.reuse() {
+            Some(group) => {
+                if let Ok(mut visibility) = visibilities.get_mut(group.root) {
+                    *visibility = Visibility::Inherited;
+                }
+                commands.entity(group.root).despawn_descendants();
+                group
+            }
+            None => CoinGroup {
+                root: commands
+                    .spawn(SpatialBundle::from_transform(Transform::from_xyz(
+                        x, 0.0, 0.6,
+                    )))
+                    .id(),
+            },
+        };
+
+        if rand::thread_rng().gen_bool(pattern.chance) {
+            let mut positions = Vec::new();
+            for i in 0..pattern.in_gap {
+                let offset = (i as f32 - (pattern.in_gap - 1) as f32 / 2.0) * COIN_SIZE * 1.5;
+                positions.push(Vec2::new(0.0, pipe + offset));
+            }
+            for i in 1..=pattern.between {
+                let t = i as f32 / (pattern.between + 1) as f32;
+                // A gentle hump between the two openings.
+                let y = before + (pipe - before) * t + (t * std::f32::consts::PI).sin() * 30.0;
+                positions.push(Vec2::new((before_x - x) * (1.0 - t), y));
+            }
+            commands.entity(group.root).with_children(|parent| {
+                for position in positions {
+                    parent.spawn((
+                        SpriteSheetBundle {
+                            texture_atlas: atlas.0.clone(),
+                            sprite: TextureAtlasSprite {
+                                custom_size: Some(Vec2::splat(COIN_SIZE)),
+                                ..TextureAtlasSprite::new(0)
+                            },
+                            transform: Transform::from_translation(position.extend(0.0)),
+                            ..default()
+                        },
+                        AnimationIndices {
+                            first: 0,
+                            last: COIN_FRAMES.len() - 1,
+                        },
+                        AnimationTimer(Timer::from_seconds(0.12, TimerMode::Repeating)),
+                        Coin,
+                    ));
+                }
+            });
+        }
+        coins.push(x, group);
+    }
+}
+
+fn collect_coins(
+    birds: Query<(&Bird, &Transform, &Hitbox), Without<Ghost>>,
+    mut coins: Query<(&GlobalTransform, &mut Visibility), With<Coin>>,
+    mut collected: EventWriter<CoinCollected>,
+) {
+    for (bird, transform, hitbox) in &birds {
+        if !bird.alive() {
+            continue;
+        }
+        for (coin, mut visibility) in &mut coins {
+            if *visibility == Visibility::Hidden {
+                continue;
+            }
+            if collide(
+                transform.translation,
+                hitbox.0,
+                coin.translation(),
+                Vec2::splat(COIN_SIZE),
+            )
+            .is_some()
+            {
+                *visibility = Visibility::Hidden;
+                collected.send(CoinCollected { value: 1 });
+            }
+        }
+    }
+}
+
+fn add_to_wallet(
+    mut collected: EventReader<CoinCollected>,
+    mut profile: ResMut<Profile>,
+    autopilot: Res<Autopilot>,
+    options: Res<Options>,
+) {
+    let total: u32 = collected.iter().map(|coin| coin.value).sum();
+    // Coins the autopilot or a replay picks up are not the player's to spend.
+    if total > 0 && !autopilot.flying() && options.playback.is_none() {
+        profile.coins += total;
+        profile.save();
+    }
+}
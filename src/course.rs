@@ -0,0 +1,227 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{DISTANCE_BETWEEN_UP_DOWN_PIPES, HEIGHT_PIPE};
+
+/// How a pipe pair moves once it is on screen.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum Behaviour {
+    #[default]
+    Static,
+    /// The whole pair bobs up and down.
+    Oscillate { amplitude: f32, period: f32 },
+    /// The opening widens and narrows around its centre.
+    Breathe { amplitude: f32, period: f32 },
+    /// The pair swings around the centre of the opening.
+    Rotate { max_angle: f32, period: f32 },
+}
+
+impl Behaviour {
+    /// Offset of the opening's centre, change of its half height and rotation
+    /// in radians, `time` seconds after the pair entered.
+    pub fn sample(&self, time: f32) -> (f32, f32, f32) {
+        let wave = |period: f32| (time * std::f32::consts::TAU / period).sin();
+        match *self {
+            Behaviour::Static => (0.0, 0.0, 0.0),
+            Behaviour::Oscillate { amplitude, period } => (amplitude * wave(period), 0.0, 0.0),
+            Behaviour::Breathe { amplitude, period } => (0.0, amplitude * wave(period), 0.0),
+            Behaviour::Rotate { max_angle, period } => (0.0, 0.0, max_angle * wave(period)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// Chance of a moving pair at the start of a run, how much it grows per
+    /// pipe passed, and where it stops.
+    fn moving_chance(&self) -> (f64, f64, f64) {
+        match self {
+            Difficulty::Easy => (0.0, 0.005, 0.25),
+            Difficulty::Normal => (0.05, 0.01, 0.5),
+            Difficulty::Hard => (0.2, 0.02, 0.8),
+        }
+    }
+}
+
+impl std::str::FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!(
+                "unknown difficulty {value:?}, expected easy, normal or hard"
+            )),
+        }
+    }
+}
+
+/// What the generator decided for one pipe pair.
+pub struct PipeSpec {
+    /// Top of the lower pipe.
+    pub top_below: f32,
+    pub behaviour: Behaviour,
+}
+
+/// Lays out the course: where each opening is and how each pair moves.
+#[derive(Resource, Clone)]
+pub struct PipeGenerator {
+    pub difficulty: Difficulty,
+    seed: u64,
+    rng: StdRng,
+}
+
+impl PipeGenerator {
+    pub fn new(difficulty: Difficulty, seed: u64) -> Self {
+        PipeGenerator {
+            difficulty,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.restart();
+    }
+
+    /// Starts the same course over from its first pipe.
+    pub fn restart(&mut self) {
+        self.rng = StdRng::seed_from_u64(self.seed);
+    }
+
+    /// Random numbers for whatever is placed around pipe `idx` besides the
+    /// pipe itself, such as power-ups. They come from the seed, so a replay
+    /// finds them where the recorded run did, but they leave the course's own
+    /// sequence alone.
+    pub fn extras(&self, idx: i32) -> StdRng {
+        StdRng::seed_from_u64(self.seed ^ (idx as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+    }
+
+    pub fn next(&mut self, idx: i32) -> PipeSpec {
+        let top_below = self.rng.gen_range(-100..-50) as f32;
+        let (start, step, max) = self.difficulty.moving_chance();
+        let chance = (start + step * idx as f64).min(max);
+        let behaviour = if self.rng.gen_bool(chance) {
+            match self.rng.gen_range(0..3) {
+                0 => Behaviour::Oscillate {
+                    amplitude: self.rng.gen_range(20.0..50.0),
+                    period: self.rng.gen_range(1.5..3.0),
+                },
+                1 => Behaviour::Breathe {
+                    amplitude: self.rng.gen_range(15.0..30.0),
+                    period: self.rng.gen_range(1.5..2.5),
+                },
+                _ => Behaviour::Rotate {
+                    max_angle: self.rng.gen_range(0.1..0.25),
+                    period: self.rng.gen_range(2.0..4.0),
+                },
+            }
+        } else {
+            Behaviour::Static
+        };
+        PipeSpec {
+            top_below,
+            behaviour,
+        }
+    }
+}
+
+/// Centres of the upper and lower part of a pair whose opening is centred on
+/// `centre`, widened by `dh` on each side and turned by `angle`.
+pub fn pipe_parts(centre: Vec2, dh: f32, angle: f32) -> [Vec2; 2] {
+    let reach = DISTANCE_BETWEEN_UP_DOWN_PIPES / 2.0 + dh + HEIGHT_PIPE / 2.0;
+    [1.0, -1.0].map(|side| centre + Vec2::from_angle(angle).rotate(Vec2::new(0.0, side * reach)))
+}
+
+/// Whether two rectangles overlap, each given by centre, half size and
+/// rotation around the z axis. Uses the separating axis test, so it works for
+/// any pair of angles.
+pub fn boxes_overlap(a: (Vec2, Vec2, f32), b: (Vec2, Vec2, f32)) -> bool {
+    let axes = |angle: f32| {
+        let (sin, cos) = angle.sin_cos();
+        [Vec2::new(cos, sin), Vec2::new(-sin, cos)]
+    };
+    let a_axes = axes(a.2);
+    let b_axes = axes(b.2);
+    let offset = b.0 - a.0;
+    a_axes.iter().chain(b_axes.iter()).all(|axis| {
+        let radius = |half: Vec2, own: [Vec2; 2]| {
+            half.x * own[0].dot(*axis).abs() + half.y * own[1].dot(*axis).abs()
+        };
+        offset.dot(*axis).abs() <= radius(a.1, a_axes) + radius(b.1, b_axes)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    use super::*;
+
+    const UNIT: Vec2 = Vec2::new(1.0, 1.0);
+
+    #[test]
+    fn boxes_that_touch_overlap() {
+        let a = (Vec2::ZERO, UNIT, 0.0);
+        assert!(boxes_overlap(a, (Vec2::new(2.0, 0.0), UNIT, 0.0)));
+        assert!(boxes_overlap(a, (Vec2::new(0.0, -2.0), UNIT, 0.0)));
+        assert!(!boxes_overlap(a, (Vec2::new(2.01, 0.0), UNIT, 0.0)));
+    }
+
+    #[test]
+    fn separated_boxes_do_not_overlap() {
+        let a = (Vec2::ZERO, UNIT, 0.0);
+        assert!(!boxes_overlap(a, (Vec2::new(3.0, 3.0), UNIT, 0.0)));
+        assert!(!boxes_overlap(a, (Vec2::new(-5.0, 0.5), UNIT, 0.3)));
+    }
+
+    #[test]
+    fn a_turned_box_reaches_further_along_its_diagonal() {
+        let turned = (Vec2::ZERO, UNIT, FRAC_PI_4);
+        let beside = (Vec2::new(2.3, 0.0), UNIT, 0.0);
+        assert!(!boxes_overlap((Vec2::ZERO, UNIT, 0.0), beside));
+        assert!(boxes_overlap(turned, beside));
+        assert!(boxes_overlap(beside, turned));
+    }
+
+    #[test]
+    fn a_turned_box_misses_what_only_its_bounds_would_hit() {
+        // The bounds of the diamond reach (1.41, 1.41), its edge does not.
+        let turned = (Vec2::ZERO, UNIT, FRAC_PI_4);
+        let corner = (Vec2::new(1.9, 1.9), Vec2::splat(0.5), 0.0);
+        assert!(!boxes_overlap(turned, corner));
+    }
+
+    #[test]
+    fn a_long_box_turned_upright_covers_what_is_above_it() {
+        let lying = (Vec2::ZERO, Vec2::new(5.0, 1.0), 0.0);
+        let standing = (Vec2::ZERO, Vec2::new(5.0, 1.0), FRAC_PI_2);
+        let above = (Vec2::new(0.0, 4.0), UNIT, 0.0);
+        assert!(!boxes_overlap(lying, above));
+        assert!(boxes_overlap(standing, above));
+    }
+
+    #[test]
+    fn a_turned_pair_keeps_its_opening_around_the_centre() {
+        let centre = Vec2::new(10.0, 20.0);
+        let [upper, lower] = pipe_parts(centre, 0.0, FRAC_PI_2);
+        assert!((upper + lower - 2.0 * centre).length() < 1e-3);
+        // Turned a quarter, the parts sit to the sides of the centre.
+        assert!((upper.y - centre.y).abs() < 1e-3);
+        assert!(upper.x < centre.x && lower.x > centre.x);
+    }
+}
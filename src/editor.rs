@@ -0,0 +1,397 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use crate::cli::Options;
+use crate::layout::{self, MainCamera, ViewBounds};
+use crate::leaderboard::no_name_entry;
+use crate::levels::{CourseReset, Level, LevelPipe, Levels, SelectedLevel};
+use crate::powerups::ActiveEffects;
+use crate::settings::menu_closed;
+use crate::{
+    is_editing, spawn_pipes, Bird, Game, ScoreText, DISTANCE_BETWEEN_UP_DOWN_PIPES,
+    DISTANCE_X_BETWEEN_PIPE, GROUND_HEIGHT, HEIGHT_SCREEN, WIDTH_PIPE,
+};
+
+/// Highest or lowest the middle of an opening may go and still leave both
+/// pipes reaching past the ground.
+const MAX_GAP: f32 = HEIGHT_SCREEN / 2.0 - GROUND_HEIGHT - DISTANCE_BETWEEN_UP_DOWN_PIPES / 2.0;
+
+#[derive(Resource, Default)]
+struct Editor {
+    /// Index of the pipe laid out at the left edge of the view.
+    first: usize,
+    selected: Option<usize>,
+    /// Offset from the cursor to the dragged pipe's opening.
+    grab: Option<Vec2>,
+    dirty: bool,
+    message: String,
+}
+
+#[derive(Component)]
+struct EditorText;
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Editor>()
+            .add_startup_system(spawn_editor_text)
+            .add_system(
+                toggle_editor
+                    .before(relayout)
+                    .run_if(menu_closed)
+                    .run_if(no_name_entry),
+            )
+            .add_systems(
+                (scroll_timeline, edit_pipes, save_level)
+                    .before(relayout)
+                    .distributive_run_if(is_editing),
+            )
+            .add_system(relayout.before(spawn_pipes).run_if(is_editing))
+            .add_system(update_editor_text);
+    }
+}
+
+/// World x of every pipe in `level` when pipe `first` sits at `start_x`.
+fn positions(level: &Level, first: usize, start_x: f32) -> Vec<f32> {
+    let mut xs = vec![start_x; level.pipes.len()];
+    for i in first + 1..xs.len() {
+        xs[i] = xs[i - 1] + level.pipes[i].spacing;
+    }
+    for i in (0..first.min(xs.len())).rev() {
+        xs[i] = xs[i + 1] - level.pipes[i + 1].spacing;
+    }
+    xs
+}
+
+/// E opens the editor on the selected level, or a new one when playing
+/// endless, also once a run is over. T test-plays from the left edge of the
+/// timeline and Escape leaves the editor.
+fn toggle_editor(
+    keyboard_input: Res<Input<KeyCode>>,
+    view: Res<ViewBounds>,
+    list: Res<Levels>,
+    mut game: ResMut<Game>,
+    mut editor: ResMut<Editor>,
+    mut selected: ResMut<SelectedLevel>,
+    mut levels: ResMut<Assets<Level>>,
+    mut effects: ResMut<ActiveEffects>,
+    mut course: CourseReset,
+    mut birds: Query<(&mut Bird, &mut Transform, &mut Visibility)>,
+    mut score_text: Query<&mut Text, With<ScoreText>>,
+) {
+    let (first, start_x, visibility) = if game.state == 5 {
+        if keyboard_input.just_pressed(KeyCode::T) {
+            (editor.first, DISTANCE_X_BETWEEN_PIPE, Visibility::Inherited)
+        } else if keyboard_input.just_pressed(KeyCode::Escape) {
+            (0, DISTANCE_X_BETWEEN_PIPE, Visibility::Inherited)
+        } else {
+            return;
+        }
+    } else if matches!(game.state, 0 | 3 | 4) && keyboard_input.just_pressed(KeyCode::E) {
+        if selected.0.is_none() {
+            let name = format!("Custom {}", list.list.len() + 1);
+            selected.0 = Some(levels.add(Level::new(name)));
+        }
+        let pipes = selected
+            .0
+            .as_ref()
+            .and_then(|handle| levels.get(handle))
+            .map_or(0, |level| level.pipes.len());
+        let first = editor.first.min(pipes.saturating_sub(1));
+        (first, view.left + WIDTH_PIPE, Visibility::Hidden)
+    } else {
+        return;
+    };
+
+    game.state = if visibility == Visibility::Hidden {
+        5
+    } else {
+        0
+    };
+    editor.first = first;
+    editor.grab = None;
+    course.reset(&mut game, first as i32 + 1, start_x);
+    *effects = ActiveEffects::default();
+    for (mut bird, mut transform, mut bird_visibility) in &mut birds {
+        *bird = Bird::default();
+        *transform = Transform::from_xyz(transform.translation.x, 0.0, 0.0);
+        *bird_visibility = visibility;
+    }
+    for mut text in &mut score_text {
+        text.sections[0].value = format!("{}", game.score);
+    }
+}
+
+fn scroll_timeline(
+    keyboard_input: Res<Input<KeyCode>>,
+    selected: Res<SelectedLevel>,
+    levels: Res<Assets<Level>>,
+    mut editor: ResMut<Editor>,
+) {
+    let Some(level) = selected.0.as_ref().and_then(|handle| levels.get(handle)) else {
+        return;
+    };
+    let step = if keyboard_input.pressed(KeyCode::LShift) {
+        5
+    } else {
+        1
+    };
+    let last = level.pipes.len().saturating_sub(1);
+    let first = if keyboard_input.just_pressed(KeyCode::Right) {
+        (editor.first + step).min(last)
+    } else if keyboard_input.just_pressed(KeyCode::Left) {
+        editor.first.saturating_sub(step)
+    } else {
+        return;
+    };
+    if first != editor.first {
+        editor.first = first;
+        editor.dirty = true;
+    }
+}
+
+/// Left click selects a pipe pair or adds one, dragging moves its opening up
+/// and down and its offset from the previous pair, right click deletes it.
+/// Pairs further along keep their place when one is moved or deleted.
+fn edit_pipes(
+    mouse: Res<Input<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    view: Res<ViewBounds>,
+    selected: Res<SelectedLevel>,
+    mut levels: ResMut<Assets<Level>>,
+    mut editor: ResMut<Editor>,
+) {
+    let (Ok(window), Ok((camera, camera_transform))) = (windows.get_single(), cameras.get_single())
+    else {
+        return;
+    };
+    let Some(cursor) = layout::cursor_world(window, camera, camera_transform) else {
+        return;
+    };
+    let Some(handle) = selected.0.clone() else {
+        return;
+    };
+    let Some(level) = levels.get(&handle) else {
+        return;
+    };
+    let start_x = view.left + WIDTH_PIPE;
+    let xs = positions(level, editor.first, start_x);
+    let gaps: Vec<f32> = level.pipes.iter().map(|pipe| pipe.gap).collect();
+    let hit = xs
+        .iter()
+        .position(|x| (x - cursor.x).abs() < WIDTH_PIPE / 2.0);
+
+    if mouse.just_released(MouseButton::Left) {
+        editor.grab = None;
+    }
+
+    if mouse.just_pressed(MouseButton::Right) {
+        let Some(i) = hit else {
+            return;
+        };
+        let Some(level) = levels.get_mut(&handle) else {
+            return;
+        };
+        let removed = level.pipes.remove(i);
+        if let Some(next) = level.pipes.get_mut(i) {
+            next.spacing += removed.spacing;
+        }
+        editor.first = editor.first.min(level.pipes.len().saturating_sub(1));
+        editor.selected = None;
+        editor.dirty = true;
+        return;
+    }
+
+    if mouse.just_pressed(MouseButton::Left) {
+        if let Some(i) = hit {
+            editor.selected = Some(i);
+            editor.grab = Some(Vec2::new(xs[i], gaps[i]) - cursor);
+            return;
+        }
+        if cursor.x <= start_x {
+            return;
+        }
+        // A new pair goes where the click was, between its neighbours.
+        let i = xs.partition_point(|x| *x < cursor.x);
+        let Some(level) = levels.get_mut(&handle) else {
+            return;
+        };
+        let spacing = i
+            .checked_sub(1)
+            .map_or(DISTANCE_X_BETWEEN_PIPE, |prev| cursor.x - xs[prev]);
+        if let Some(next) = level.pipes.get_mut(i) {
+            next.spacing = xs[i] - cursor.x;
+        }
+        let gap = cursor.y.clamp(-MAX_GAP, MAX_GAP);
+        level.pipes.insert(i, LevelPipe::new(gap, spacing));
+        editor.selected = Some(i);
+        editor.grab = Some(Vec2::ZERO);
+        editor.dirty = true;
+        return;
+    }
+
+    let (Some(i), Some(grab)) = (editor.selected, editor.grab) else {
+        return;
+    };
+    if !mouse.pressed(MouseButton::Left) || i >= xs.len() {
+        return;
+    }
+    let target = cursor + grab;
+    let gap = target.y.clamp(-MAX_GAP, MAX_GAP);
+    // The pair at the left edge anchors the layout, so it only moves up and down.
+    let x = if i > editor.first {
+        let low = xs[i - 1] + WIDTH_PIPE;
+        let high = xs
+            .get(i + 1)
+            .map_or(f32::INFINITY, |next| next - WIDTH_PIPE);
+        target.x.clamp(low, high.max(low))
+    } else {
+        xs[i]
+    };
+    if gap == gaps[i] && x == xs[i] {
+        return;
+    }
+    let Some(level) = levels.get_mut(&handle) else {
+        return;
+    };
+    level.pipes[i].gap = gap;
+    level.pipes[i].spacing += x - xs[i];
+    if let Some(next) = level.pipes.get_mut(i + 1) {
+        next.spacing -= x - xs[i];
+    }
+    editor.dirty = true;
+}
+
+/// Ctrl+S writes the level back to its file under the assets' `levels/`, or
+/// to a new one named after the level.
+fn save_level(
+    keyboard_input: Res<Input<KeyCode>>,
+    asset_server: Res<AssetServer>,
+    selected: Res<SelectedLevel>,
+    levels: Res<Assets<Level>>,
+    mut list: ResMut<Levels>,
+    mut editor: ResMut<Editor>,
+    options: Res<Options>,
+) {
+    let ctrl = keyboard_input.any_pressed([KeyCode::LControl, KeyCode::RControl]);
+    if !ctrl || !keyboard_input.just_pressed(KeyCode::S) {
+        return;
+    }
+    let Some(handle) = selected.0.as_ref() else {
+        return;
+    };
+    let Some(level) = levels.get(handle) else {
+        return;
+    };
+    let path = asset_server
+        .get_handle_path(handle)
+        .map(|path| path.path().to_owned())
+        .unwrap_or_else(|| {
+            let slug = level.name.to_lowercase().replace(' ', "-");
+            format!("levels/{slug}.level.ron").into()
+        });
+    editor.message = match write_level(level, &options.assets, &path) {
+        Ok(()) => {
+            if !list.list.contains(handle) {
+                list.list.push(handle.clone());
+            }
+            format!("saved {}", path.display())
+        }
+        Err(err) => {
+            warn!("could not save level to {}: {err}", path.display());
+            format!("could not save: {err}")
+        }
+    };
+}
+
+/// Writes `level` to `path` in the asset folder `assets`, found the way the
+/// asset server finds it.
+#[cfg(not(target_arch = "wasm32"))]
+fn write_level(level: &Level, assets: &str, path: &std::path::Path) -> Result<(), String> {
+    let text = ron::ser::to_string_pretty(level, default()).map_err(|err| err.to_string())?;
+    let path = bevy::asset::FileAssetIo::get_base_path()
+        .join(assets)
+        .join(path);
+    std::fs::write(path, text).map_err(|err| err.to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_level(_level: &Level, _assets: &str, _path: &std::path::Path) -> Result<(), String> {
+    Err("levels cannot be saved in the browser".to_string())
+}
+
+/// Lays the edited level out again from the left edge of the view.
+fn relayout(
+    view: Res<ViewBounds>,
+    mut game: ResMut<Game>,
+    mut editor: ResMut<Editor>,
+    mut course: CourseReset,
+) {
+    if !editor.dirty && !view.is_changed() {
+        return;
+    }
+    editor.dirty = false;
+    course.reset(&mut game, editor.first as i32 + 1, view.left + WIDTH_PIPE);
+}
+
+fn spawn_editor_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 20.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(20.0),
+                top: Val::Px(100.0),
+                ..default()
+            },
+            ..default()
+        }),
+        EditorText,
+    ));
+}
+
+fn update_editor_text(
+    game: Res<Game>,
+    editor: Res<Editor>,
+    selected: Res<SelectedLevel>,
+    levels: Res<Assets<Level>>,
+    mut text: Query<&mut Text, With<EditorText>>,
+) {
+    let level = selected.0.as_ref().and_then(|handle| levels.get(handle));
+    let value = match (game.state, level) {
+        (5, Some(level)) => {
+            let mut value = format!(
+                "Editing {} ({} pipes)\n\
+                 click: add/select  drag: move  right click: delete\n\
+                 Left/Right: scroll  T: test from here  Ctrl+S: save  Esc: done\n",
+                level.name,
+                level.pipes.len()
+            );
+            if let Some(pipe) = editor.selected.and_then(|i| level.pipes.get(i)) {
+                value.push_str(&format!(
+                    "pipe {}: gap {:.0}  offset {:.0}\n",
+                    editor.selected.unwrap_or(0) + 1,
+                    pipe.gap,
+                    pipe.spacing
+                ));
+            }
+            value.push_str(&editor.message);
+            value
+        }
+        (3 | 4, Some(_)) => "E: edit this level".to_string(),
+        _ => String::new(),
+    };
+    for mut text in &mut text {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! A reinforcement-learning environment around the race simulation, in the
+//! shape of a gym: `reset` a seeded course, then `step` one tick at a time.
+//! The bird falls and collides through the same code as the game's birds, so
+//! what an agent learns here carries over.
+
+use bevy::prelude::Vec2;
+
+use crate::course::Difficulty;
+use crate::sim::{Race, Rules, BIRD_SIZE};
+use crate::{DISTANCE_BETWEEN_UP_DOWN_PIPES, WIDTH_PIPE};
+
+/// Reward for every tick the bird stays up.
+pub const ALIVE_REWARD: f32 = 0.1;
+/// Reward for every pipe passed.
+pub const PIPE_REWARD: f32 = 1.0;
+/// Reward on the tick the bird goes down.
+pub const CRASH_REWARD: f32 = -1.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    NoOp,
+    Flap,
+}
+
+/// What the agent sees after each tick, in world units with y pointing up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Observation {
+    pub bird_y: f32,
+    /// Vertical speed, up is positive.
+    pub velocity: f32,
+    /// Horizontal distance from the bird to the middle of the next pipe it
+    /// has not cleared yet.
+    pub gap_distance: f32,
+    /// Highest and lowest y of that pipe's opening.
+    pub gap_top: f32,
+    pub gap_bottom: f32,
+}
+
+impl Observation {
+    /// What a bird at `bird` moving at `velocity` sees of `pipes`, each given
+    /// left to right as x, middle of its opening and how far the opening is
+    /// widened on each side.
+    pub fn new(
+        bird: Vec2,
+        velocity: f32,
+        pipes: impl IntoIterator<Item = (f32, f32, f32)>,
+    ) -> Self {
+        let clear = bird.x - BIRD_SIZE.x / 2.0 - WIDTH_PIPE / 2.0;
+        let next = pipes.into_iter().find(|(x, _, _)| *x >= clear);
+        let (gap_distance, gap_top, gap_bottom) = match next {
+            Some((x, gap, dh)) => {
+                let half = DISTANCE_BETWEEN_UP_DOWN_PIPES / 2.0 + dh;
+                (x - bird.x, gap + half, gap - half)
+            }
+            None => (f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY),
+        };
+        Observation {
+            bird_y: bird.y,
+            velocity,
+            gap_distance,
+            gap_top,
+            gap_bottom,
+        }
+    }
+}
+
+pub struct Env {
+    difficulty: Difficulty,
+    rules: Rules,
+    race: Race,
+}
+
+impl Env {
+    pub fn new(difficulty: Difficulty) -> Self {
+        Env::with_rules(difficulty, Rules::default())
+    }
+
+    pub fn with_rules(difficulty: Difficulty, rules: Rules) -> Self {
+        Env {
+            difficulty,
+            rules,
+            race: Env::fresh_race(difficulty, rules, 0),
+        }
+    }
+
+    fn fresh_race(difficulty: Difficulty, rules: Rules, seed: u64) -> Race {
+        let mut race = Race::with_rules(seed, difficulty, rules);
+        race.add_bird(0.0);
+        race.start();
+        race
+    }
+
+    /// Starts over on the course `seed` lays out.
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.race = Env::fresh_race(self.difficulty, self.rules, seed);
+        self.observe()
+    }
+
+    /// Plays one tick. Once `done`, further steps change nothing until the
+    /// next `reset`.
+    pub fn step(&mut self, action: Action) -> (Observation, f32, bool) {
+        if self.done() {
+            return (self.observe(), 0.0, true);
+        }
+        let score = self.race.birds[0].score;
+        if action == Action::Flap {
+            self.race.flap(0);
+        }
+        self.race.step();
+        let bird = &self.race.birds[0];
+        let reward = if bird.alive() {
+            ALIVE_REWARD + PIPE_REWARD * (bird.score - score) as f32
+        } else {
+            CRASH_REWARD
+        };
+        (self.observe(), reward, self.done())
+    }
+
+    /// The episode ends as soon as the bird hits anything.
+    pub fn done(&self) -> bool {
+        !self.race.birds[0].alive()
+    }
+
+    pub fn score(&self) -> i32 {
+        self.race.birds[0].score
+    }
+
+    pub fn tick(&self) -> u32 {
+        self.race.tick
+    }
+
+    pub fn race(&self) -> &Race {
+        &self.race
+    }
+
+    pub fn observe(&self) -> Observation {
+        let bird = &self.race.birds[0];
+        let pipes = self
+            .race
+            .pipes
+            .iter()
+            .map(|pipe| (pipe.x, pipe.gap, pipe.dh));
+        Observation::new(Vec2::new(bird.x, bird.y), bird.speed, pipes)
+    }
+}
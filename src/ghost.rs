@@ -0,0 +1,479 @@
+use bevy::{app::AppExit, prelude::*, window::FileDragAndDrop};
+
+use crate::autopilot::Autopilot;
+use crate::cli::Options;
+use crate::levels::{CourseReset, SelectedLevel};
+use crate::obstacles::PipeGenerator;
+use crate::online::Online;
+use crate::pool::Pool;
+use crate::powerups::{ActiveEffects, PickupSlot};
+use crate::profile::Profile;
+use crate::replay::Replay;
+use crate::skins::{Hitbox, Skinned, Skins};
+use crate::versus::Versus;
+use crate::{
+    bird_movement, check_for_collisions, crash, flap, flap_birds, is_playing, Bird, Flapped, Game,
+    Pipe, Player, DISTANCE_X_BETWEEN_PIPE,
+};
+
+/// A bird that replays recorded flaps instead of listening to input. It flies
+/// through the same course but nothing collides with it.
+#[derive(Component)]
+pub struct Ghost {
+    flaps: Vec<u32>,
+    end: u32,
+    next: usize,
+}
+
+impl Ghost {
+    fn new(replay: &Replay) -> Self {
+        Ghost {
+            flaps: replay.flaps.clone(),
+            end: replay.end,
+            next: 0,
+        }
+    }
+}
+
+#[derive(Component)]
+struct GhostText;
+
+/// The run the ghost replays and the one being recorded to beat it. Ghosts
+/// only race in endless mode.
+#[derive(Resource, Default)]
+struct Runs {
+    ghost: Option<Replay>,
+    best_score: Option<i32>,
+    recording: Option<Replay>,
+}
+
+/// A finished run of the player's own, recorded so it can be flown again.
+pub struct RunRecorded {
+    pub replay: Replay,
+}
+
+/// A run given with `--replay`, flown by the first player's bird instead of
+/// input.
+#[derive(Resource)]
+struct Playback {
+    replay: Replay,
+    next: usize,
+}
+
+pub struct GhostPlugin;
+
+impl Plugin for GhostPlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(replay) = app.world.resource::<Options>().playback.clone() {
+            app.insert_resource(Playback { replay, next: 0 })
+                .add_system(
+                    play_back
+                        .before(flap_birds)
+                        .in_schedule(CoreSchedule::FixedUpdate),
+                )
+                .add_system(finish_playback);
+        }
+        app.init_resource::<Runs>()
+            .add_event::<RunRecorded>()
+            .add_startup_system(load_best)
+            .add_startup_system(spawn_ghost_text)
+            .add_system(
+                replay_ghost
+                    .after(flap_birds)
+                    .before(bird_movement)
+                    .run_if(is_playing)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(record_flaps)
+            .add_system(
+                record_shield
+                    .after(check_for_collisions)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(finish_recording.after(record_flaps))
+            .add_system(import_replay)
+            .add_system(reset_ghost.after(import_replay))
+            .add_system(fade_ghost)
+            .add_system(update_ghost_text);
+    }
+}
+
+/// Makes `replay` the run to race: the course follows its seed and the ghost
+/// its flaps.
+fn race(
+    commands: &mut Commands,
+    runs: &mut Runs,
+    generator: &mut PipeGenerator,
+    ghosts: &Query<Entity, With<Ghost>>,
+    skin: &'static str,
+    replay: Replay,
+) {
+    generator.difficulty = replay.difficulty;
+    generator.reseed(replay.seed);
+    let ghost = Ghost::new(&replay);
+    match ghosts.iter().next() {
+        Some(entity) => {
+            commands.entity(entity).insert(ghost);
+        }
+        None => {
+            commands.spawn((
+                SpriteSheetBundle::default(),
+                Skinned::new(skin),
+                Bird::default(),
+                ghost,
+            ));
+        }
+    }
+    runs.ghost = Some(replay);
+}
+
+fn load_best(
+    mut commands: Commands,
+    mut runs: ResMut<Runs>,
+    mut generator: ResMut<PipeGenerator>,
+    ghosts: Query<Entity, With<Ghost>>,
+    skins: Res<Skins>,
+    profile: Res<Profile>,
+    options: Res<Options>,
+    playback: Option<Res<Playback>>,
+) {
+    if let Some(playback) = playback {
+        generator.difficulty = playback.replay.difficulty;
+        generator.reseed(playback.replay.seed);
+        return;
+    }
+    // A course picked on the command line is not the best run's.
+    if options.seed.is_some() || options.difficulty.is_some() {
+        return;
+    }
+    let Some(path) = Replay::best_path().filter(|path| path.exists()) else {
+        return;
+    };
+    match Replay::load(&path) {
+        Ok(replay) => {
+            runs.best_score = Some(replay.score);
+            let skin = skins.get(&profile.skin).name;
+            race(
+                &mut commands,
+                &mut runs,
+                &mut generator,
+                &ghosts,
+                skin,
+                replay,
+            );
+        }
+        Err(err) => warn!("ignoring unreadable replay {}: {err}", path.display()),
+    }
+}
+
+/// Dropping a replay file on the window races against it instead, as long as
+/// the run has not started.
+fn import_replay(
+    mut commands: Commands,
+    mut dropped: EventReader<FileDragAndDrop>,
+    mut runs: ResMut<Runs>,
+    mut game: ResMut<Game>,
+    mut selected: ResMut<SelectedLevel>,
+    mut course: CourseReset,
+    ghosts: Query<Entity, With<Ghost>>,
+    skins: Res<Skins>,
+    profile: Res<Profile>,
+) {
+    for event in dropped.iter() {
+        let FileDragAndDrop::DroppedFile { path_buf, .. } = event else {
+            continue;
+        };
+        if game.state != 0 {
+            warn!("drop replays before the first flap");
+            continue;
+        }
+        let replay = match Replay::load(path_buf) {
+            Ok(replay) => replay,
+            Err(err) => {
+                warn!("could not import replay {}: {err}", path_buf.display());
+                continue;
+            }
+        };
+        info!("racing {} (score {})", path_buf.display(), replay.score);
+        let skin = skins.get(&profile.skin).name;
+        race(
+            &mut commands,
+            &mut runs,
+            course.generator(),
+            &ghosts,
+            skin,
+            replay,
+        );
+        selected.0 = None;
+        course.reset(&mut game, 1, DISTANCE_X_BETWEEN_PIPE);
+    }
+}
+
+/// Puts the ghost back at the start whenever a run is about to begin, and
+/// hides it outside endless mode.
+fn reset_ghost(
+    game: Res<Game>,
+    selected: Res<SelectedLevel>,
+    mut ghosts: Query<(&mut Ghost, &mut Bird, &mut Transform, &mut Visibility)>,
+) {
+    if game.state != 0 || !game.is_changed() {
+        return;
+    }
+    for (mut ghost, mut bird, mut transform, mut visibility) in &mut ghosts {
+        ghost.next = 0;
+        *bird = Bird::default();
+        *transform = Transform::default();
+        *visibility = if selected.0.is_none() {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+fn replay_ghost(
+    game: Res<Game>,
+    selected: Res<SelectedLevel>,
+    mut ghosts: Query<(&mut Ghost, &mut Bird, &mut Transform)>,
+) {
+    if selected.0.is_some() {
+        return;
+    }
+    for (mut ghost, mut bird, mut transform) in &mut ghosts {
+        if !bird.alive() {
+            continue;
+        }
+        // The recorded bird still moved on the tick it crashed.
+        if game.tick > ghost.end {
+            bird.down_at = Some(game.tick);
+            crash(&mut bird, &mut transform);
+            continue;
+        }
+        while ghost
+            .flaps
+            .get(ghost.next)
+            .is_some_and(|&tick| tick <= game.tick)
+        {
+            ghost.next += 1;
+            flap(&mut bird, &mut transform);
+        }
+    }
+}
+
+fn record_flaps(
+    mut flapped: EventReader<Flapped>,
+    selected: Res<SelectedLevel>,
+    versus: Res<Versus>,
+    online: Option<Res<Online>>,
+    autopilot: Res<Autopilot>,
+    playback: Option<Res<Playback>>,
+    generator: Res<PipeGenerator>,
+    players: Query<(&Hitbox, &Player)>,
+    mut runs: ResMut<Runs>,
+) {
+    // Handing the bird to the autopilot mid-run makes the run the autopilot's.
+    if autopilot.flying() && runs.recording.is_some() {
+        runs.recording = None;
+    }
+    for event in flapped.iter() {
+        // A shared, replayed or flown-for-you run is nobody's personal best.
+        if selected.0.is_some()
+            || versus.on
+            || online.is_some()
+            || playback.is_some()
+            || autopilot.flying()
+        {
+            continue;
+        }
+        if event.tick == 0 {
+            let Some((hitbox, _)) = players.iter().find(|(_, player)| player.index == 0) else {
+                continue;
+            };
+            runs.recording = Some(Replay {
+                seed: generator.seed(),
+                difficulty: generator.difficulty,
+                flaps: Vec::new(),
+                end: 0,
+                score: 0,
+                hitbox: hitbox.0.into(),
+                shielded: None,
+            });
+        }
+        if let Some(recording) = &mut runs.recording {
+            recording.flaps.push(event.tick);
+        }
+    }
+}
+
+/// Notes the tick on which a shield first saves the bird being recorded.
+fn record_shield(game: Res<Game>, effects: Res<ActiveEffects>, mut runs: ResMut<Runs>) {
+    let Some(recording) = &mut runs.recording else {
+        return;
+    };
+    if recording.shielded.is_none() && effects.is_active("shield_grace") {
+        recording.shielded = Some(game.tick);
+    }
+}
+
+/// Flaps the first player's bird on the ticks the replay did, with the
+/// hitbox it was recorded with. Its first flap
+/// starts the run, once the pipes and pickups it flew past are laid out.
+fn play_back(
+    game: Res<Game>,
+    mut playback: ResMut<Playback>,
+    pipes: Res<Pool<Pipe>>,
+    pickups: Res<Pool<PickupSlot>>,
+    mut players: Query<(&mut Bird, &mut Hitbox, &Player)>,
+) {
+    if game.state != 0 && game.state != 1 {
+        return;
+    }
+    let size = Vec2::from(playback.replay.hitbox);
+    for (_, mut hitbox, player) in &mut players {
+        if player.index == 0 && hitbox.0 != size {
+            hitbox.0 = size;
+        }
+    }
+    if game.state == 0 && (pipes.live().next().is_none() || pickups.live().next().is_none()) {
+        return;
+    }
+    let tick = if game.state == 0 { 0 } else { game.tick };
+    let Playback { replay, next } = &mut *playback;
+    while replay.flaps.get(*next).is_some_and(|&flap| flap <= tick) {
+        *next += 1;
+        for (mut bird, _, player) in &mut players {
+            if player.index == 0 {
+                bird.flap = true;
+            }
+        }
+    }
+}
+
+/// Reports how the replayed run went against the recording, and quits once
+/// it is over when nobody is watching.
+fn finish_playback(
+    game: Res<Game>,
+    playback: Res<Playback>,
+    options: Res<Options>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if !game.is_changed() || game.state != 3 {
+        return;
+    }
+    let replay = &playback.replay;
+    if game.score == replay.score {
+        info!("replay finished with score {}", game.score);
+    } else {
+        warn!(
+            "replay finished with score {} but was recorded with {}",
+            game.score, replay.score
+        );
+    }
+    if options.headless {
+        exit.send(AppExit);
+    }
+}
+
+/// Once the bird is down, keeps the run if it beats the best one and races
+/// against it from then on.
+fn finish_recording(
+    mut commands: Commands,
+    game: Res<Game>,
+    mut runs: ResMut<Runs>,
+    mut generator: ResMut<PipeGenerator>,
+    ghosts: Query<Entity, With<Ghost>>,
+    skins: Res<Skins>,
+    mut profile: ResMut<Profile>,
+    players: Query<(&Bird, &Player)>,
+    mut recorded: EventWriter<RunRecorded>,
+) {
+    if game.state != 2 && game.state != 3 {
+        return;
+    }
+    let Some(mut recording) = runs.recording.take() else {
+        return;
+    };
+    // A bird that hits a pipe is down a tick before the game stops flying.
+    recording.end = players
+        .iter()
+        .find(|(_, player)| player.index == 0)
+        .and_then(|(bird, _)| bird.down_at)
+        .unwrap_or(game.tick);
+    recording.score = game.score;
+    recorded.send(RunRecorded {
+        replay: recording.clone(),
+    });
+    if recording.score > profile.high_score {
+        profile.high_score = recording.score;
+        profile.save();
+    }
+    if runs.best_score.is_some_and(|best| recording.score <= best) {
+        return;
+    }
+    info!("new personal best: {}", recording.score);
+    runs.best_score = Some(recording.score);
+    if let Some(path) = Replay::best_path() {
+        if let Err(err) = recording.save(&path) {
+            warn!("could not save replay to {}: {err}", path.display());
+        }
+    }
+    let skin = skins.get(&profile.skin).name;
+    race(
+        &mut commands,
+        &mut runs,
+        &mut generator,
+        &ghosts,
+        skin,
+        recording,
+    );
+}
+
+fn fade_ghost(mut ghosts: Query<&mut TextureAtlasSprite, With<Ghost>>) {
+    for mut sprite in &mut ghosts {
+        if sprite.color.a() != 0.4 {
+            sprite.color.set_a(0.4);
+        }
+    }
+}
+
+fn spawn_ghost_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 28.0,
+                color: Color::rgba(1.0, 1.0, 1.0, 0.6),
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.0),
+                top: Val::Px(75.0),
+                ..default()
+            },
+            ..default()
+        }),
+        GhostText,
+    ));
+}
+
+fn update_ghost_text(
+    runs: Res<Runs>,
+    selected: Res<SelectedLevel>,
+    ghosts: Query<&Bird, With<Ghost>>,
+    mut text: Query<&mut Text, With<GhostText>>,
+) {
+    let value = match (&runs.ghost, ghosts.iter().next()) {
+        (Some(replay), Some(bird)) if selected.0.is_none() => {
+            format!("ghost {} / {}", bird.score, replay.score)
+        }
+        _ => String::new(),
+    };
+    for mut text in &mut text {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use bevy::{
+    prelude::*,
+    render::camera::{CameraUpdateSystem, ScalingMode, Viewport},
+    window::PrimaryWindow,
+};
+
+/// How the virtual resolution is mapped onto the window.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ScaleMode {
+    /// Scale as large as the window allows, adding black bars on the sides
+    /// (pillarbox) or top and bottom (letterbox) to keep the aspect ratio.
+    Fit,
+    /// Like `Fit`, but only by whole multiples so pixel art stays crisp.
+    Integer,
+    /// Fill the window with a fixed virtual height, showing more or less of
+    /// the world horizontally depending on the aspect ratio.
+    Expand,
+}
+
+#[derive(Resource)]
+pub struct Layout {
+    pub width: f32,
+    pub height: f32,
+    pub mode: ScaleMode,
+}
+
+/// World-space rectangle currently visible through the main camera.
+#[derive(Resource, Debug, PartialEq)]
+pub struct ViewBounds {
+    pub left: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub top: f32,
+}
+
+impl ViewBounds {
+    pub fn width(&self) -> f32 {
+        self.right - self.left
+    }
+}
+
+#[derive(Component)]
+pub struct MainCamera;
+
+/// World position under the mouse, taking the bars around the viewport into
+/// account.
+pub fn cursor_world(window: &Window, camera: &Camera, transform: &GlobalTransform) -> Option<Vec2> {
+    let cursor = window.cursor_position()?;
+    let (min, max) = camera.logical_viewport_rect()?;
+    // The cursor is measured from the bottom of the window, the viewport from the top.
+    let inside = Vec2::new(cursor.x, window.height() - cursor.y) - min;
+    let viewport = Vec2::new(inside.x, (max - min).y - inside.y);
+    camera.viewport_to_world_2d(transform, viewport)
+}
+
+pub struct LayoutPlugin;
+
+impl Plugin for LayoutPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ClearColor(Color::BLACK))
+            .add_startup_system(spawn_camera)
+            .add_system(cycle_scale_mode)
+            .add_system(fit_viewport.after(cycle_scale_mode))
+            .add_system(
+                update_view_bounds
+                    .in_base_set(CoreSet::PostUpdate)
+                    .after(CameraUpdateSystem),
+            );
+    }
+}
+
+fn spawn_camera(mut commands: Commands, layout: Res<Layout>) {
+    let mut camera = Camera2dBundle::default();
+    camera.projection.scaling_mode = scaling_mode(&layout);
+    commands.spawn((camera, MainCamera));
+    commands.insert_resource(ViewBounds {
+        left: -layout.width / 2.0,
+        right: layout.width / 2.0,
+        bottom: -layout.height / 2.0,
+        top: layout.height / 2.0,
+    });
+}
+
+fn scaling_mode(layout: &Layout) -> ScalingMode {
+    match layout.mode {
+        ScaleMode::Fit | ScaleMode::Integer => ScalingMode::Fixed {
+            width: layout.width,
+            height: layout.height,
+        },
+        ScaleMode::Expand => ScalingMode::FixedVertical(layout.height),
+    }
+}
+
+fn cycle_scale_mode(keyboard_input: Res<Input<KeyCode>>, mut layout: ResMut<Layout>) {
+    if keyboard_input.just_pressed(KeyCode::F8) {
+        layout.mode = match layout.mode {
+            ScaleMode::Fit => ScaleMode::Integer,
+            ScaleMode::Integer => ScaleMode::Expand,
+            ScaleMode::Expand => ScaleMode::Fit,
+        };
+        info!("scale mode: {:?}", layout.mode);
+    }
+}
+
+/// Restricts the camera to a centred rectangle with the virtual aspect ratio;
+/// whatever is left of the window is cleared to black.
+fn fit_viewport(
+    layout: Res<Layout>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<(&mut Camera, &mut OrthographicProjection), With<MainCamera>>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let Ok((mut camera, mut projection)) = cameras.get_single_mut() else {
+        return;
+    };
+    if layout.is_changed() {
+        projection.scaling_mode = scaling_mode(&layout);
+    }
+
+    let window_size = UVec2::new(window.physical_width(), window.physical_height());
+    let viewport = match layout.mode {
+        ScaleMode::Expand => None,
+        ScaleMode::Fit | ScaleMode::Integer => {
+            let mut scale = f32::min(
+                window_size.x as f32 / layout.width,
+                window_size.y as f32 / layout.height,
+            );
+            if layout.mode == ScaleMode::Integer {
+                scale = scale.floor().max(1.0);
+            }
+            let size = (Vec2::new(layout.width, layout.height) * scale)
+                .as_uvec2()
+                .min(window_size)
+                .max(UVec2::ONE);
+            Some(Viewport {
+                physical_position: (window_size - size) / 2,
+                physical_size: size,
+                ..default()
+            })
+        }
+    };
+
+    let unchanged = match (&camera.viewport, &viewport) {
+        (Some(old), Some(new)) => {
+            old.physical_position == new.physical_position && old.physical_size == new.physical_size
+        }
+        (None, None) => true,
+        _ => false,
+    };
+    if !unchanged {
+        camera.viewport = viewport;
+    }
+}
+
+fn update_view_bounds(
+    mut view: ResMut<ViewBounds>,
+    cameras: Query<(&OrthographicProjection, &Transform), With<MainCamera>>,
+) {
+    let Ok((projection, transform)) = cameras.get_single() else {
+        return;
+    };
+    let area = projection.area;
+    if area.width() <= 0.0 || area.height() <= 0.0 {
+        return;
+    }
+    let bounds = ViewBounds {
+        left: transform.translation.x + area.min.x,
+        right: transform.translation.x + area.max.x,
+        bottom: transform.translation.y + area.min.y,
+        top: transform.translation.y + area.max.y,
+    };
+    if *view != bounds {
+        *view = bounds;
+    }
+}
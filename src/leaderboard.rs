@@ -0,0 +1,396 @@
+use bevy::{prelude::*, window::ReceivedCharacter};
+use my_bevy_game::course::Difficulty;
+use my_bevy_game::save::{self, Folder, Versioned};
+use serde::{Deserialize, Serialize};
+
+use crate::ghost::RunRecorded;
+use crate::uploads::Uploads;
+use crate::Game;
+
+const FILE: &str = "leaderboard.ron";
+/// Entries kept on the board.
+const TOP: usize = 10;
+/// Letters in a name.
+const INITIALS: usize = 3;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Entry {
+    pub name: String,
+    pub score: i32,
+    /// Local date and time the run ended.
+    pub date: String,
+    pub seed: u64,
+    pub difficulty: Difficulty,
+    /// Saved file holding the run's replay, next to the leaderboard.
+    pub replay: Option<String>,
+}
+
+/// The best endless runs flown on this machine, best first.
+#[derive(Resource, Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Leaderboard {
+    pub entries: Vec<Entry>,
+    /// Initials typed last time, offered again for the next entry.
+    pub last_name: String,
+}
+
+impl Versioned for Leaderboard {
+    const VERSION: u32 = 1;
+
+    fn migrate(version: u32, _text: &str) -> Result<Self, String> {
+        Err(format!("no leaderboard schema {version}"))
+    }
+}
+
+impl Leaderboard {
+    pub fn load() -> Self {
+        let Some(storage) = save::platform_storage(Folder::Data) else {
+            return Leaderboard::default();
+        };
+        let loaded = save::load(storage.as_ref(), FILE);
+        for problem in &loaded.problems {
+            warn!("skipping unreadable leaderboard: {problem}");
+        }
+        loaded.data.unwrap_or_default()
+    }
+
+    pub fn qualifies(&self, score: i32) -> bool {
+        score > 0
+            && (self.entries.len() < TOP
+                || self.entries.last().is_some_and(|last| score > last.score))
+    }
+
+    /// Puts `entry` below the ones with the same score or better, saves the
+    /// board and returns the entry's place. The replays of entries pushed
+    /// off the bottom are deleted.
+    fn insert(&mut self, entry: Entry) -> usize {
+        let place = self
+            .entries
+            .iter()
+            .position(|other| entry.score > other.score)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(place, entry);
+        let dropped = self.entries.split_off(self.entries.len().min(TOP));
+        let Some(mut storage) = save::platform_storage(Folder::Data) else {
+            return place;
+        };
+        for replay in dropped.iter().filter_map(|entry| entry.replay.as_ref()) {
+            if let Err(err) = storage.remove(replay) {
+                warn!("could not delete replay {replay}: {err}");
+            }
+        }
+        if let Err(err) = save::save(storage.as_mut(), FILE, self) {
+            warn!("could not save leaderboard: {err}");
+        }
+        place
+    }
+}
+
+/// A run good enough for the board, waiting for the player's initials.
+#[derive(Resource)]
+pub struct NameEntry {
+    entry: Entry,
+    replay: String,
+    name: String,
+}
+
+/// Keeps typed initials from reaching the game's own keys.
+pub fn no_name_entry(entry: Option<Res<NameEntry>>) -> bool {
+    entry.is_none()
+}
+
+/// L shows the board on the title screen or after a run, as does the
+/// settings menu. `highlight` marks a new entry.
+#[derive(Resource, Default)]
+pub struct LeaderboardScreen {
+    pub open: bool,
+    highlight: Option<usize>,
+}
+
+#[derive(Component)]
+struct LeaderboardRoot;
+
+#[derive(Component)]
+struct LeaderboardText;
+
+#[derive(Component)]
+struct GameOverText;
+
+pub struct LeaderboardPlugin;
+
+impl Plugin for LeaderboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Leaderboard::load())
+            .init_resource::<LeaderboardScreen>()
+            .add_startup_system(spawn_leaderboard)
+            .add_startup_system(spawn_game_over_text)
+            .add_system(offer_entry)
+            .add_system(
+                type_name
+                    .after(offer_entry)
+                    .run_if(resource_exists::<NameEntry>()),
+            )
+            .add_system(toggle_leaderboard.run_if(no_name_entry))
+            .add_system(
+                update_leaderboard
+                    .after(type_name)
+                    .after(toggle_leaderboard),
+            )
+            .add_system(update_game_over_text);
+    }
+}
+
+/// Asks for initials when a recorded run makes the board.
+fn offer_entry(
+    mut commands: Commands,
+    mut recorded: EventReader<RunRecorded>,
+    leaderboard: Res<Leaderboard>,
+) {
+    for RunRecorded { replay } in recorded.iter() {
+        if !leaderboard.qualifies(replay.score) {
+            continue;
+        }
+        let now = chrono::Local::now();
+        let replay_file = format!("replay-{}.ron", now.format("%Y%m%d-%H%M%S"));
+        let replay_text = match ron::to_string(replay) {
+            Ok(text) => text,
+            Err(err) => {
+                warn!("could not keep the replay for the leaderboard: {err}");
+                String::new()
+            }
+        };
+        commands.insert_resource(NameEntry {
+            entry: Entry {
+                name: String::new(),
+                score: replay.score,
+                date: now.format("%Y-%m-%d %H:%M").to_string(),
+                seed: replay.seed,
+                difficulty: replay.difficulty,
+                replay: (!replay_text.is_empty()).then_some(replay_file),
+            },
+            replay: replay_text,
+            name: leaderboard.last_name.clone(),
+        });
+    }
+}
+
+/// Letters and digits type, Backspace takes one back, Enter puts the run on
+/// the board and Escape lets it go.
+fn type_name(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut typed: EventReader<ReceivedCharacter>,
+    mut pending: ResMut<NameEntry>,
+    mut leaderboard: ResMut<Leaderboard>,
+    mut screen: ResMut<LeaderboardScreen>,
+) {
+    for character in typed.iter() {
+        if character.char.is_ascii_alphanumeric() && pending.name.len() < INITIALS {
+            pending.name.push(character.char.to_ascii_uppercase());
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        pending.name.pop();
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        commands.remove_resource::<NameEntry>();
+        return;
+    }
+    if !keyboard_input.just_pressed(KeyCode::Return) || pending.name.is_empty() {
+        return;
+    }
+    let mut entry = pending.entry.clone();
+    entry.name = pending.name.clone();
+    if let Some(file) = &entry.replay {
+        let result = save::platform_storage(Folder::Data)
+            .ok_or_else(|| "nowhere to save".to_string())
+            .and_then(|mut storage| storage.write(file, &pending.replay));
+        if let Err(err) = result {
+            warn!("could not save replay {file}: {err}");
+            entry.replay = None;
+        }
+    }
+    info!("{} takes the board with {}", entry.name, entry.score);
+    leaderboard.last_name = entry.name.clone();
+    let place = leaderboard.insert(entry);
+    commands.remove_resource::<NameEntry>();
+    screen.open = true;
+    screen.highlight = Some(place);
+}
+
+fn toggle_leaderboard(
+    keyboard_input: Res<Input<KeyCode>>,
+    game: Res<Game>,
+    mut screen: ResMut<LeaderboardScreen>,
+) {
+    if !matches!(game.state, 0 | 3) {
+        if screen.open {
+            screen.open = false;
+        }
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::L) {
+        screen.open = !screen.open;
+        screen.highlight = None;
+    }
+}
+
+fn spawn_leaderboard(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::width(Val::Percent(100.0)),
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        top: Val::Px(0.0),
+                        bottom: Val::Px(0.0),
+                        ..default()
+                    },
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+                visibility: Visibility::Hidden,
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            LeaderboardRoot,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 24.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                LeaderboardText,
+            ));
+        });
+}
+
+/// Shows the initials prompt while one is pending, otherwise the board and,
+/// with `--scoreboard`, the online one.
+fn update_leaderboard(
+    screen: Res<LeaderboardScreen>,
+    leaderboard: Res<Leaderboard>,
+    pending: Option<Res<NameEntry>>,
+    uploads: Option<Res<Uploads>>,
+    mut roots: Query<&mut Visibility, With<LeaderboardRoot>>,
+    mut text: Query<&mut Text, With<LeaderboardText>>,
+) {
+    let prompt_changed = pending.as_ref().is_some_and(|pending| pending.is_changed());
+    let online_changed = uploads.as_ref().is_some_and(|uploads| uploads.is_changed());
+    if !(screen.is_changed() || leaderboard.is_changed() || prompt_changed || online_changed) {
+        return;
+    }
+    let visible = pending.is_some() || screen.open;
+    for mut visibility in &mut roots {
+        let wanted = if visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != wanted {
+            *visibility = wanted;
+        }
+    }
+    let value = if let Some(pending) = &pending {
+        let blanks = "_".repeat(INITIALS - pending.name.len());
+        format!(
+            "New high score: {}\n\nEnter your initials: {}{blanks}\n\n\
+             Enter save, Backspace erase, Escape skip",
+            pending.entry.score, pending.name
+        )
+    } else if screen.open {
+        let mut value = "Leaderboard\n\n".to_string();
+        if leaderboard.entries.is_empty() {
+            value.push_str("No runs yet\n");
+        }
+        for (place, entry) in leaderboard.entries.iter().enumerate() {
+            let marker = if screen.highlight == Some(place) {
+                ">"
+            } else {
+                " "
+            };
+            value.push_str(&format!(
+                "{marker}{:>2}. {:<3} {:>4}  {}  {:?} #{}\n",
+                place + 1,
+                entry.name,
+                entry.score,
+                entry.date,
+                entry.difficulty,
+                entry.seed
+            ));
+        }
+        if let Some(uploads) = &uploads {
+            value.push_str("\nOnline\n\n");
+            if uploads.top.is_empty() {
+                value.push_str("No scores yet\n");
+            }
+            for (place, entry) in uploads.top.iter().enumerate() {
+                value.push_str(&format!(
+                    " {:>2}. {:<16} {:>4}\n",
+                    place + 1,
+                    entry.name,
+                    entry.score
+                ));
+            }
+            if uploads.waiting > 0 {
+                value.push_str(&format!("{} scores waiting to be sent\n", uploads.waiting));
+            }
+        }
+        value.push_str("\nL close");
+        value
+    } else {
+        return;
+    };
+    for mut text in &mut text {
+        text.sections[0].value = value.clone();
+    }
+}
+
+fn spawn_game_over_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 24.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_text_alignment(TextAlignment::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.0),
+                top: Val::Px(200.0),
+                ..default()
+            },
+            ..default()
+        }),
+        GameOverText,
+    ));
+}
+
+/// Points to the board and the stats once a run is over.
+fn update_game_over_text(game: Res<Game>, mut text: Query<&mut Text, With<GameOverText>>) {
+    if !game.is_changed() {
+        return;
+    }
+    let value = if game.state == 3 {
+        "Game over\nL leaderboard, F4 stats"
+    } else {
+        ""
+    };
+    for mut text in &mut text {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.to_string();
+        }
+    }
+}
@@ -0,0 +1,484 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    ecs::system::SystemParam,
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::autopilot::Autopilot;
+use crate::coins::{CoinCollected, CoinGroup};
+use crate::obstacles::{Behaviour, PipeGenerator, PipeSpec};
+use crate::parallax::Parallax;
+use crate::pool::Pool;
+use crate::powerups::PickupSlot;
+use crate::profile::Profile;
+use crate::{
+    is_playing, spawn_pipes, Bird, Game, Pipe, Player, DISTANCE_BETWEEN_UP_DOWN_PIPES,
+    DISTANCE_X_BETWEEN_PIPE, GROUND_HEIGHT, HEIGHT_SCREEN, SCROLL_SPEED,
+};
+
+/// A finite course, read from a `.level.ron` file under `assets/levels/`.
+#[derive(TypeUuid, Serialize, Deserialize, Debug)]
+#[uuid = "2061545d-1a5e-4aa2-aa73-8ed2e4ca94a0"]
+pub struct Level {
+    pub name: String,
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    /// Distance from the last pipe to the finish line.
+    #[serde(default = "default_spacing")]
+    pub finish: f32,
+    /// Coins needed for the second and third star; reaching the finish earns
+    /// the first.
+    #[serde(default)]
+    pub stars: [u32; 2],
+    pub pipes: Vec<LevelPipe>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LevelPipe {
+    /// Height of the middle of the opening.
+    pub gap: f32,
+    /// Distance from the previous pipe; the first pipe always enters at the
+    /// usual place.
+    #[serde(default = "default_spacing")]
+    pub spacing: f32,
+    /// Scroll speed from the moment this pipe is passed.
+    #[serde(default)]
+    pub speed: Option<f32>,
+    #[serde(default)]
+    pub obstacle: Behaviour,
+}
+
+fn default_speed() -> f32 {
+    SCROLL_SPEED
+}
+
+fn default_spacing() -> f32 {
+    DISTANCE_X_BETWEEN_PIPE
+}
+
+impl LevelPipe {
+    pub fn new(gap: f32, spacing: f32) -> Self {
+        LevelPipe {
+            gap,
+            spacing,
+            speed: None,
+            obstacle: Behaviour::Static,
+        }
+    }
+}
+
+impl Level {
+    pub fn new(name: String) -> Self {
+        Level {
+            name,
+            speed: SCROLL_SPEED,
+            finish: DISTANCE_X_BETWEEN_PIPE,
+            stars: [4, 8],
+            pipes: (0..3)
+                .map(|_| LevelPipe::new(0.0, DISTANCE_X_BETWEEN_PIPE))
+                .collect(),
+        }
+    }
+
+    /// Scroll speed once `passed` pipes are behind the bird.
+    fn speed_after(&self, passed: usize) -> f32 {
+        self.pipes[..passed.min(self.pipes.len())]
+            .iter()
+            .rev()
+            .find_map(|pipe| pipe.speed)
+            .unwrap_or(self.speed)
+    }
+
+    fn stars_for(&self, coins: u32) -> u8 {
+        1 + self.stars.iter().filter(|&&needed| coins >= needed).count() as u8
+    }
+}
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let level: Level = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+/// Every level found in `assets/levels/`, ordered by file name.
+#[derive(Resource, Default)]
+pub struct Levels {
+    pub list: Vec<Handle<Level>>,
+}
+
+/// The level being played, or `None` for the endless course.
+#[derive(Resource, Default)]
+pub struct SelectedLevel(pub Option<Handle<Level>>);
+
+#[derive(Resource, Default)]
+struct LevelRun {
+    coins: u32,
+    finish: Option<Entity>,
+    stars: u8,
+}
+
+#[derive(Component)]
+struct FinishLine;
+
+#[derive(Component)]
+struct LevelText;
+
+/// Clears the pipes and everything laid out with them, so the course starts
+/// over from a given pipe.
+#[derive(SystemParam)]
+pub struct CourseReset<'w, 's> {
+    commands: Commands<'w, 's>,
+    run: ResMut<'w, LevelRun>,
+    pipes: ResMut<'w, Pool<Pipe>>,
+    coins: ResMut<'w, Pool<CoinGroup>>,
+    pickups: ResMut<'w, Pool<PickupSlot>>,
+    generator: ResMut<'w, PipeGenerator>,
+}
+
+impl<'w, 's> CourseReset<'w, 's> {
+    pub fn generator(&mut self) -> &mut PipeGenerator {
+        &mut self.generator
+    }
+
+    /// Lays pipe `first` out at `start_x` next, counting the ones before it as
+    /// already passed.
+    pub fn reset(&mut self, game: &mut Game, first: i32, start_x: f32) {
+        self.pipes.clear(&mut self.commands);
+        self.coins.clear(&mut self.commands);
+        self.pickups.clear(&mut self.commands);
+        self.pipes.start_x = start_x;
+        if let Some(finish) = self.run.finish {
+            self.commands.entity(finish).despawn();
+        }
+        *self.run = LevelRun::default();
+        self.generator.restart();
+        game.current_inc = first - 1;
+        game.score = first - 1;
+        game.tick = 0;
+    }
+}
+
+/// Where the next pipes come from: the selected level, or the generator when
+/// playing endless.
+#[derive(SystemParam)]
+pub struct Course<'w> {
+    selected: Res<'w, SelectedLevel>,
+    levels: Res<'w, Assets<Level>>,
+    generator: ResMut<'w, PipeGenerator>,
+}
+
+impl<'w> Course<'w> {
+    /// Distance from the previous pipe to pipe `idx`, or `None` when there is
+    /// no such pipe yet: the level has run out or is still loading.
+    pub fn spacing(&self, idx: i32) -> Option<f32> {
+        match &self.selected.0 {
+            None => Some(DISTANCE_X_BETWEEN_PIPE),
+            Some(handle) => {
+                let level = self.levels.get(handle)?;
+                level.pipes.get(idx as usize - 1).map(|pipe| pipe.spacing)
+            }
+        }
+    }
+
+    /// Pipe `idx`, which `spacing` must have reported.
+    pub fn next(&mut self, idx: i32) -> PipeSpec {
+        let level = self.selected.0.as_ref().and_then(|h| self.levels.get(h));
+        match level {
+            Some(level) => {
+                let pipe = &level.pipes[idx as usize - 1];
+                PipeSpec {
+                    top_below: pipe.gap - DISTANCE_BETWEEN_UP_DOWN_PIPES / 2.0,
+                    behaviour: pipe.obstacle,
+                }
+            }
+            None => self.generator.next(idx),
+        }
+    }
+}
+
+pub struct LevelsPlugin;
+
+impl Plugin for LevelsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Level>()
+            .init_asset_loader::<LevelLoader>()
+            .init_resource::<SelectedLevel>()
+            .init_resource::<LevelRun>()
+            .add_startup_system(load_levels)
+            .add_startup_system(spawn_level_text)
+            .add_system(select_level)
+            .add_system(apply_level_speed.after(select_level))
+            .add_system(spawn_finish.after(spawn_pipes))
+            .add_system(
+                scroll_finish
+                    .run_if(is_playing)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(count_coins.run_if(is_playing))
+            .add_system(cross_finish.after(scroll_finish).run_if(is_playing))
+            .add_system(update_level_text);
+    }
+}
+
+fn load_levels(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let mut list: Vec<Handle<Level>> = match asset_server.load_folder("levels") {
+        Ok(handles) => handles.into_iter().map(|handle| handle.typed()).collect(),
+        Err(err) => {
+            warn!("no levels available: {err}");
+            Vec::new()
+        }
+    };
+    list.sort_by_key(|handle| {
+        asset_server
+            .get_handle_path(handle)
+            .map(|path| path.path().to_owned())
+    });
+    commands.insert_resource(Levels { list });
+}
+
+/// Tab and Shift+Tab pick endless or one of the levels before the first flap. The
+/// pipes already waiting on screen are swapped for the new course.
+fn select_level(
+    keyboard_input: Res<Input<KeyCode>>,
+    levels: Res<Levels>,
+    mut selected: ResMut<SelectedLevel>,
+    mut game: ResMut<Game>,
+    mut course: CourseReset,
+) {
+    if game.state != 0 {
+        return;
+    }
+    if !keyboard_input.just_pressed(KeyCode::Tab) {
+        return;
+    }
+    let step = if keyboard_input.pressed(KeyCode::LShift) {
+        -1
+    } else {
+        1
+    };
+    // Entry 0 is endless, the levels follow.
+    let current = selected
+        .0
+        .as_ref()
+        .and_then(|handle| levels.list.iter().position(|level| level == handle))
+        .map_or(0, |index| index as isize + 1);
+    let next = (current + step).rem_euclid(levels.list.len() as isize + 1) as usize;
+    selected.0 = next.checked_sub(1).map(|index| levels.list[index].clone());
+
+    course.reset(&mut game, 1, DISTANCE_X_BETWEEN_PIPE);
+}
+
+/// Levels may change the scroll speed as pipes are passed; everything that
+/// scrolls with the pipes follows.
+fn apply_level_speed(
+    game: Res<Game>,
+    selected: Res<SelectedLevel>,
+    levels: Res<Assets<Level>>,
+    mut pipes: ResMut<Pool<Pipe>>,
+    mut coins: ResMut<Pool<CoinGroup>>,
+    mut pickups: ResMut<Pool<PickupSlot>>,
+    mut parallax: ResMut<Parallax>,
+) {
+    if !game.is_changed() && !selected.is_changed() {
+        return;
+    }
+    let level = selected.0.as_ref().and_then(|handle| levels.get(handle));
+    let speed = level.map_or(SCROLL_SPEED, |level| {
+        level.speed_after(game.score.max(0) as usize)
+    });
+    if pipes.speed != speed {
+        pipes.speed = speed;
+        coins.speed = speed;
+        pickups.speed = speed;
+        parallax.speed = speed;
+    }
+}
+
+fn spawn_finish(
+    mut commands: Commands,
+    game: Res<Game>,
+    selected: Res<SelectedLevel>,
+    levels: Res<Assets<Level>>,
+    mut pipes: ResMut<Pool<Pipe>>,
+    mut run: ResMut<LevelRun>,
+) {
+    if run.finish.is_some() {
+        return;
+    }
+    let Some(level) = selected.0.as_ref().and_then(|handle| levels.get(handle)) else {
+        return;
+    };
+    if game.current_inc as usize != level.pipes.len() {
+        return;
+    }
+    pipes.spacing = level.finish;
+    let Some(x) = pipes.next_entry() else {
+        return;
+    };
+    let height = HEIGHT_SCREEN - GROUND_HEIGHT * 2.0;
+    run.finish = Some(
+        commands
+            .spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgba(1.0, 1.0, 1.0, 0.8),
+                        custom_size: Some(Vec2::new(8.0, height)),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(x, 0.0, 0.5),
+                    ..default()
+                },
+                FinishLine,
+            ))
+            .id(),
+    );
+}
+
+fn scroll_finish(
+    fixed_time: Res<FixedTime>,
+    pipes: Res<Pool<Pipe>>,
+    mut finish: Query<&mut Transform, With<FinishLine>>,
+) {
+    for mut transform in &mut finish {
+        transform.translation.x -= pipes.speed * fixed_time.period.as_secs_f32();
+    }
+}
+
+fn count_coins(mut collected: EventReader<CoinCollected>, mut run: ResMut<LevelRun>) {
+    run.coins += collected.iter().map(|coin| coin.value).sum::<u32>();
+}
+
+/// Reaching the finish line ends the run and rates it.
+fn cross_finish(
+    mut game: ResMut<Game>,
+    mut run: ResMut<LevelRun>,
+    mut profile: ResMut<Profile>,
+    selected: Res<SelectedLevel>,
+    levels: Res<Assets<Level>>,
+    birds: Query<(&Bird, &Transform), With<Player>>,
+    finish: Query<&Transform, With<FinishLine>>,
+    autopilot: Res<Autopilot>,
+) {
+    let Ok(finish) = finish.get_single() else {
+        return;
+    };
+    let Some(level) = selected.0.as_ref().and_then(|handle| levels.get(handle)) else {
+        return;
+    };
+    // The first bird still flying across the line finishes the run.
+    let crossed = birds
+        .iter()
+        .any(|(bird, transform)| bird.alive() && finish.translation.x <= transform.translation.x);
+    if !crossed {
+        return;
+    }
+    game.state = 4;
+    run.stars = level.stars_for(run.coins);
+    let best = profile.level_stars.entry(level.name.clone()).or_default();
+    if run.stars > *best && !autopilot.flying() {
+        *best = run.stars;
+        profile.save();
+    }
+}
+
+fn spawn_level_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 26.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(20.0),
+                top: Val::Px(140.0),
+                ..default()
+            },
+            ..default()
+        }),
+        LevelText,
+    ));
+}
+
+fn star_row(stars: u8) -> String {
+    format!(
+        "{}{}",
+        "*".repeat(stars as usize),
+        "-".repeat(3 - stars as usize)
+    )
+}
+
+/// The course list while waiting for the first flap, the rating once a level
+/// is finished, and nothing in between.
+fn update_level_text(
+    game: Res<Game>,
+    run: Res<LevelRun>,
+    profile: Res<Profile>,
+    levels: Res<Levels>,
+    selected: Res<SelectedLevel>,
+    assets: Res<Assets<Level>>,
+    mut text: Query<&mut Text, With<LevelText>>,
+) {
+    let value = match game.state {
+        0 => {
+            let mut lines = vec![(None, "Endless".to_string())];
+            for handle in &levels.list {
+                let Some(level) = assets.get(handle) else {
+                    continue;
+                };
+                let best = profile.level_stars.get(&level.name).copied().unwrap_or(0);
+                lines.push((Some(handle), format!("{}  {}", level.name, star_row(best))));
+            }
+            let mut value = String::from("Tab/Shift+Tab: course\n");
+            for (handle, line) in lines {
+                let marker = if handle == selected.0.as_ref() {
+                    "> "
+                } else {
+                    "  "
+                };
+                value.push_str(&format!("{marker}{line}\n"));
+            }
+            value
+        }
+        4 => {
+            let name = selected
+                .0
+                .as_ref()
+                .and_then(|handle| assets.get(handle))
+                .map_or("", |level| level.name.as_str());
+            format!(
+                "{name} cleared!\n{}  {} coins",
+                star_row(run.stars),
+                run.coins
+            )
+        }
+        _ => String::new(),
+    };
+    for mut text in &mut text {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
@@ -0,0 +1,23 @@
+//! The parts of the game that run without a window: the course, the race
+//! simulation, the network protocol, save files and the online leaderboard.
+//! The game, the race server and other tools share them so that every one of
+//! them flies the same course.
+
+pub mod course;
+pub mod env;
+pub mod net;
+pub mod pilot;
+pub mod replay;
+pub mod save;
+pub mod scoreboard;
+pub mod sim;
+pub mod spectate;
+
+pub const SCROLL_SPEED: f32 = 150.0;
+pub const GROUND_HEIGHT: f32 = 121.0;
+pub const WIDTH_SCREEN: f32 = 736.0;
+pub const HEIGHT_SCREEN: f32 = 576.0;
+pub const DISTANCE_BETWEEN_UP_DOWN_PIPES: f32 = 160.0;
+pub const HEIGHT_PIPE: f32 = 319.0;
+pub const WIDTH_PIPE: f32 = 54.0;
+pub const DISTANCE_X_BETWEEN_PIPE: f32 = 300.0;
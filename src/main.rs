@@ -1,32 +1,169 @@
-use bevy::sprite::collide_aabb::collide;
+// Bevy systems routinely take many parameters with nested query types.
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
+use atlas::{Art, ArtLoader, AtlasPlugin};
+use autopilot::{Autopilot, AutopilotPlugin};
+use bevy::prelude::*;
+use cli::{Options, OptionsPlugin};
+use coins::CoinsPlugin;
+use editor::EditorPlugin;
+use ghost::{Ghost, GhostPlugin};
+use layout::{Layout, LayoutPlugin, ScaleMode, ViewBounds};
+use leaderboard::LeaderboardPlugin;
+use levels::{Course, LevelsPlugin};
+use my_bevy_game::course::pipe_parts;
+use my_bevy_game::sim::{self, Cause, Landing, SimPipe, CRASH_SPEED, FLAP_SPEED, GRAVITY};
+use my_bevy_game::{
+    replay, DISTANCE_BETWEEN_UP_DOWN_PIPES, DISTANCE_X_BETWEEN_PIPE, GROUND_HEIGHT, HEIGHT_SCREEN,
+    SCROLL_SPEED, WIDTH_PIPE, WIDTH_SCREEN,
+};
+use obstacles::ObstaclesPlugin;
+use online::OnlinePlugin;
+use parallax::{Parallax, ParallaxLayer};
+use pool::{Pool, Poolable};
+use powerups::{ActiveEffects, PowerUpsPlugin};
+use profile::Profile;
+use settings::{menu_closed, Settings, SettingsPlugin};
+use skins::{Hitbox, Skinned, Skins, SkinsPlugin};
+use spectator_plugin::SpectatorPlugin;
+use stats::StatsPlugin;
+use theme::{ThemePlugin, Themed, Themes};
+use uploads::UploadsPlugin;
+use versus::VersusPlugin;
+
+mod atlas;
+mod autopilot;
+mod cli;
+mod coins;
+mod editor;
+mod ghost;
+mod layout;
+mod leaderboard;
+mod levels;
+mod obstacles;
+mod online;
+mod parallax;
+mod pool;
+mod powerups;
+mod profile;
+mod settings;
+mod skins;
+mod spectator_plugin;
+mod stats;
+mod theme;
+mod uploads;
+mod versus;
 
-use bevy::{prelude::*, window::PresentMode};
-use rand::Rng;
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
-            primary_window: Some(Window {
-                title: "Flappy Bird".into(),
-                resolution: (736., 576.).into(),
-                present_mode: PresentMode::AutoVsync,
-                // Tells wasm to resize the window according to the available canvas
-                fit_canvas_to_parent: true,
-                // Tells wasm not to override default event handling, like F5, Ctrl+R etc.
-                prevent_default_event_handling: false,
-                ..default()
-            }),
-            ..default()
-        }))
-        .add_event::<CollisionEvent>()
+    let options = Options::from_args();
+    let settings = SettingsPlugin::load();
+    game(options, settings).run();
+}
+
+/// The whole game, set up the way `options` and the saved settings ask.
+fn game(options: Options, settings: SettingsPlugin) -> App {
+    let assets = options.assets.clone();
+    let mut app = App::new();
+    app.add_plugins(options.plugins(&settings.settings))
+        .add_plugin(OptionsPlugin(options))
         .add_startup_system(setup)
         .init_resource::<Game>()
-        .add_system(sprite_movement)
+        .insert_resource(Layout {
+            width: WIDTH_SCREEN,
+            height: HEIGHT_SCREEN,
+            mode: ScaleMode::Fit,
+        })
+        .add_plugin(LayoutPlugin)
+        .add_plugin(AtlasPlugin)
+        .add_plugin(ThemePlugin)
+        .insert_resource(Profile::load(&assets))
+        .add_plugin(SkinsPlugin)
+        .add_plugin(CoinsPlugin)
+        .add_plugin(PowerUpsPlugin)
+        .add_plugin(settings)
+        .add_plugin(ObstaclesPlugin)
+        .add_plugin(LevelsPlugin)
+        .add_plugin(EditorPlugin)
+        .add_plugin(GhostPlugin)
+        .add_plugin(VersusPlugin)
+        .add_plugin(OnlinePlugin)
+        .add_plugin(SpectatorPlugin)
+        .add_plugin(AutopilotPlugin)
+        .add_plugin(StatsPlugin)
+        .add_plugin(LeaderboardPlugin)
+        .add_plugin(UploadsPlugin)
+        .insert_resource(
+            Pool::<Pipe>::new(6, DISTANCE_X_BETWEEN_PIPE, DISTANCE_X_BETWEEN_PIPE)
+                .with_speed(SCROLL_SPEED),
+        )
+        .insert_resource(Parallax {
+            speed: SCROLL_SPEED,
+            view_width: WIDTH_SCREEN,
+            fade_secs: 1.0,
+            layers: vec![
+                ParallaxLayer {
+                    art: Art::Image("data/background.png"),
+                    color: Color::WHITE,
+                    speed: 0.4,
+                    z: 0.0,
+                    y: 0.0,
+                    flip_y: false,
+                    themed: Some(Themed::Background),
+                },
+                ParallaxLayer {
+                    art: Art::Image("image/ground.png"),
+                    color: Color::WHITE,
+                    speed: 1.0,
+                    z: 10.0,
+                    y: HEIGHT_SCREEN / 2.0 - GROUND_HEIGHT / 2.0,
+                    flip_y: true,
+                    themed: Some(Themed::Ground),
+                },
+                ParallaxLayer {
+                    art: Art::Image("image/ground.png"),
+                    color: Color::WHITE,
+                    speed: 1.0,
+                    z: 10.0,
+                    y: -(HEIGHT_SCREEN / 2.0 - GROUND_HEIGHT / 2.0),
+                    flip_y: false,
+                    themed: Some(Themed::Ground),
+                },
+            ],
+        })
+        .add_system(parallax::sync_layers.run_if(resource_changed::<Parallax>()))
+        .add_system(parallax::tile_layers.after(parallax::sync_layers))
+        .add_system(parallax::fade_layers.after(parallax::tile_layers))
+        .add_system(theme_milestones)
+        .add_system(skin_milestones)
+        .add_system(choose_skin.run_if(menu_closed))
+        .add_event::<Flapped>()
+        // The run itself advances in fixed ticks so that replays of the same
+        // flaps on the same seed play out the same way.
+        .add_systems(
+            (
+                flap_birds,
+                bird_movement,
+                check_for_collisions,
+                sprite_movement,
+                advance_tick,
+            )
+                .chain()
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        .add_systems(
+            (pool::scroll::<Pipe>, parallax::scroll)
+                .distributive_run_if(is_playing)
+                .after(flap_birds)
+                .before(bird_movement)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        .add_system(pool::recycle::<Pipe>.run_if(is_playing))
+        .add_system(fit_to_view.run_if(resource_changed::<ViewBounds>()))
+        .add_system(spawn_pipes.after(fit_to_view))
         .add_system(touch_system)
-        .add_system(mouse_click_system)
-        .add_system(animate_sprite)
-        .add_system(bird_movement)
-        .add_system(check_for_collisions)
-        .run();
+        .add_system(mouse_click_system.run_if(menu_closed))
+        .add_system(animate_sprite);
+    app
 }
 
 #[derive(Component)]
@@ -37,7 +174,6 @@ enum Direction {
 #[derive(Component, PartialEq, Eq)]
 enum ObjectTag {
     Pipe,
-    Background,
     Bird,
 }
 
@@ -55,27 +191,76 @@ struct Bird {
     acc: f32,
     acc_rotation: f32,
     gohell: bool,
+    /// Set by input to flap on the next tick.
+    flap: bool,
+    /// Pipes this bird has passed.
+    score: i32,
+    /// Tick on which the bird hit something or the ground.
+    down_at: Option<u32>,
+    /// What brought the bird down, when it is known.
+    cause: Option<Cause>,
+    /// Pressed against the top of the screen this tick.
+    ceiling: bool,
 }
-#[derive(Default)]
-struct PipePart {
-    entity: Option<Entity>,
+
+impl Bird {
+    fn alive(&self) -> bool {
+        self.down_at.is_none()
+    }
+}
+
+impl Default for Bird {
+    fn default() -> Self {
+        Bird {
+            speed: FLAP_SPEED,
+            acc: GRAVITY,
+            acc_rotation: -60.0,
+            gohell: false,
+            flap: false,
+            score: 0,
+            down_at: None,
+            cause: None,
+            ceiling: false,
+        }
+    }
 }
-#[derive(Default)]
 struct Pipe {
-    upper: PipePart,
-    below: PipePart,
-    idx: i32,
+    upper: Entity,
+    below: Entity,
+    /// The pair as the simulation sees it, which collisions and scoring go by.
+    sim: SimPipe,
 }
+
+impl Poolable for Pipe {
+    fn entities(&self) -> Vec<Entity> {
+        vec![self.upper, self.below]
+    }
+}
+
+/// A bird flown by someone at this machine.
+#[derive(Component)]
+struct Player {
+    index: usize,
+    key: KeyCode,
+}
+
 #[derive(Resource, Default)]
 struct Game {
-    pipes: Vec<Pipe>,
+    /// 0 waiting for the first flap, 1 flying, 2 falling after a hit, 3 dead,
+    /// 4 past a level's finish line, 5 in the level editor.
     state: i32,
+    /// Best score among the players' birds, which each keep their own.
     score: i32,
     current_inc: i32,
+    /// Simulation ticks since the first flap.
+    tick: u32,
 }
 
-#[derive(Default)]
-struct CollisionEvent;
+/// Player `player`'s bird flapped during tick `tick` of the run.
+struct Flapped {
+    tick: u32,
+    player: usize,
+}
 
 #[derive(Component)]
 struct ScoreText;
@@ -83,15 +268,6 @@ struct ScoreText;
 #[derive(Component, Deref, DerefMut)]
 struct AnimationTimer(Timer);
 
-const GROUND_HEIGHT: f32 = 121.0;
-//const WIDTH_BACKGROUND: f32 = 736.0;
-const HALF_WIDTH_BACKGROUND: f32 = 736.0 / 2.0;
-const HEIGHT_SCREEN: f32 = 576.0;
-const DISTANCE_BETWEEN_UP_DOWN_PIPES: f32 = 160.0;
-const HEIGHT_PIPE: f32 = 319.0;
-const DISTANCE_X_BETWEEN_PIPE: f32 = 300.0;
-const MIN_SCREEN: f32 = -552.0;
-const BIRTH_HEIGHT: f32 = 26.0;
 fn animate_sprite(
     time: Res<Time>,
     mut query: Query<(
@@ -115,319 +291,384 @@ fn animate_sprite(
 fn setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-    mut game: ResMut<Game>,
+    themes: Res<Themes>,
+    skins: Res<Skins>,
+    profile: Res<Profile>,
+    settings: Res<Settings>,
 ) {
-    commands.spawn(Camera2dBundle::default());
-
-    for x in 0..4 {
-        commands.spawn((
-            SpriteBundle {
-                texture: asset_server.load("data/background.png"),
-                transform: Transform {
-                    // We need to convert our Vec2 into a Vec3, by giving it a z-coordinate
-                    // This is used to determine the order of our sprites
-                    translation: Vec3::new((x as f32 - 1.0) * HALF_WIDTH_BACKGROUND, 0.0, 0.0),
-                    // The z-scale of 2D objects must always be 1.0,
-                    // or their ordering will be affected in surprising ways.
-                    // See https://github.com/bevyengine/bevy/issues/4149
-                    scale: Vec3::new(1.0, 1.0, 1.0),
-                    ..default()
-                },
-                ..default()
-            },
-            Direction::Up,
-            ObjectTag::Background,
-        ));
-        commands.spawn((
-            SpriteBundle {
-                texture: asset_server.load("image/ground.png"),
-                transform: Transform {
-                    // We need to convert our Vec2 into a Vec3, by giving it a z-coordinate
-                    // This is used to determine the order of our sprites
-                    translation: Vec3::new(
-                        (x as f32 - 1.0) * HALF_WIDTH_BACKGROUND,
-                        HEIGHT_SCREEN / 2.0 - GROUND_HEIGHT / 2.0,
-                        10.0,
-                    ),
-                    // The z-scale of 2D objects must always be 1.0,
-                    // or their ordering will be affected in surprising ways.
-                    // See https://github.com/bevyengine/bevy/issues/4149
-                    scale: Vec3::new(1.0, -1.0, 1.0),
-                    ..default()
-                },
-                ..default()
-            },
-            Direction::Up,
-            ObjectTag::Background,
-        ));
-
-        commands.spawn((
-            SpriteBundle {
-                texture: asset_server.load("image/ground.png"),
-                transform: Transform {
-                    // We need to convert our Vec2 into a Vec3, by giving it a z-coordinate
-                    // This is used to determine the order of our sprites
-                    translation: Vec3::new(
-                        (x as f32 - 1.0) * HALF_WIDTH_BACKGROUND,
-                        -(HEIGHT_SCREEN / 2.0 - GROUND_HEIGHT / 2.0),
-                        10.0,
-                    ),
-                    // The z-scale of 2D objects must always be 1.0,
-                    // or their ordering will be affected in surprising ways.
-                    // See https://github.com/bevyengine/bevy/issues/4149
-                    scale: Vec3::new(1.0, 1.0, 1.0),
-                    ..default()
-                },
-                ..default()
+    // The skin fills in the atlas and animation once its art has loaded.
+    let skin = skins.get(&profile.skin);
+
+    commands.spawn((
+        SpriteSheetBundle {
+            transform: Transform::from_scale(Vec3::splat(1.0)),
+            ..default()
+        },
+        Skinned::new(skin.name),
+        Hitbox(skin.hitbox),
+        Bird::default(),
+        Player {
+            index: 0,
+            key: settings.flap_key(0),
+        },
+        ObjectTag::Bird,
+        Collider,
+        Themed::Bird,
+    ));
+
+    commands.spawn((
+        // Create a TextBundle that has a Text with a single section.
+        TextBundle::from_section(
+            // Accepts a `String` or any type that converts into a `String`, such as `&str`
+            "0",
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 70.0,
+                color: themes.theme().text,
             },
-            Direction::Up,
-            ObjectTag::Background,
-        ));
-    }
+        ) // Set the alignment of the Text
+        .with_text_alignment(TextAlignment::Center)
+        // Set the style of the TextBundle itself.
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+
+            ..default()
+        }),
+        ScoreText,
+        Themed::Text,
+    ));
+}
 
-    for x in 0..6 {
-        let top_below_pipe = rand::thread_rng().gen_range(-100..-50);
+fn is_playing(game: Res<Game>) -> bool {
+    game.state == 1
+}
 
-        let y_below_pipe = top_below_pipe as f32 - HEIGHT_PIPE / 2.0;
-        let y_above_pipe =
-            top_below_pipe as f32 + DISTANCE_BETWEEN_UP_DOWN_PIPES + HEIGHT_PIPE / 2.0;
-        let bellow = commands
+fn is_editing(game: Res<Game>) -> bool {
+    game.state == 5
+}
+
+/// Keeps pipes entering just beyond the right edge of whatever the camera
+/// shows, and the scenery wide enough to cover it.
+fn fit_to_view(
+    view: Res<ViewBounds>,
+    mut pipes: ResMut<Pool<Pipe>>,
+    mut parallax: ResMut<Parallax>,
+) {
+    pipes.exit_x = view.left - WIDTH_PIPE;
+    pipes.enter_x = view.right + DISTANCE_X_BETWEEN_PIPE;
+    pipes.budget = (view.width() / DISTANCE_X_BETWEEN_PIPE).ceil() as usize + 2;
+    parallax.view_width = view.width();
+}
+
+fn spawn_pipes(
+    mut commands: Commands,
+    art: ArtLoader,
+    themes: Res<Themes>,
+    mut pipes: ResMut<Pool<Pipe>>,
+    mut parts: Query<(
+        &mut Transform,
+        &mut Visibility,
+        &mut Sprite,
+        &mut Handle<Image>,
+    )>,
+    mut game: ResMut<Game>,
+    mut course: Course,
+) {
+    // Pipes may come from the atlas, so wait until the current theme's art is in.
+    let Some(pipe_art) = art.resolve(themes.theme().pipe) else {
+        return;
+    };
+    while let Some(spacing) = course.spacing(game.current_inc + 1) {
+        pipes.spacing = spacing;
+        let Some(x) = pipes.next_entry() else {
+            break;
+        };
+        game.current_inc += 1;
+        let spec = course.next(game.current_inc);
+        let sim = SimPipe::new(x, game.current_inc, &spec, 0.0);
+        let rotation = Quat::from_rotation_z(sim.angle);
+        let [above, below] = pipe_parts(Vec2::new(x, sim.gap), sim.dh, sim.angle);
+
+        if let Some(mut pipe) = pipes.reuse() {
+            for (entity, centre) in [(pipe.upper, above), (pipe.below, below)] {
+                if let Ok((mut transform, mut visibility, mut sprite, mut texture)) =
+                    parts.get_mut(entity)
+                {
+                    transform.translation.x = centre.x;
+                    transform.translation.y = centre.y;
+                    transform.rotation = rotation;
+                    *visibility = Visibility::Inherited;
+                    sprite.rect = pipe_art.rect;
+                    *texture = pipe_art.texture.clone();
+                }
+            }
+            pipe.sim = sim;
+            pipes.push(x, pipe);
+            continue;
+        }
+
+        let below = commands
             .spawn((
                 SpriteBundle {
-                    texture: asset_server.load("image/pipe2.png"),
+                    sprite: Sprite {
+                        rect: pipe_art.rect,
+                        ..default()
+                    },
+                    texture: pipe_art.texture.clone(),
                     transform: Transform {
                         // We need to convert our Vec2 into a Vec3, by giving it a z-coordinate
                         // This is used to determine the order of our sprites
-                        translation: Vec3::new(
-                            (x as f32 + 1.0) * DISTANCE_X_BETWEEN_PIPE,
-                            y_below_pipe,
-                            0.5,
-                        ),
+                        translation: below.extend(0.5),
                         // The z-scale of 2D objects must always be 1.0,
                         // or their ordering will be affected in surprising ways.
                         // See https://github.com/bevyengine/bevy/issues/4149
                         scale: Vec3::new(1.0, 1.0, 1.0),
-                        ..default()
+                        rotation,
                     },
                     ..default()
                 },
                 Direction::Up,
                 ObjectTag::Pipe,
                 Collider,
+                Themed::Pipe,
             ))
             .id();
 
         let upper = commands
             .spawn((
                 SpriteBundle {
-                    texture: asset_server.load("image/pipe2.png"),
+                    sprite: Sprite {
+                        rect: pipe_art.rect,
+                        ..default()
+                    },
+                    texture: pipe_art.texture.clone(),
                     transform: Transform {
-                        // We need to convert our Vec2 into a Vec3, by giving it a z-coordinate
-                        // This is used to determine the order of our sprites
-                        translation: Vec3::new(
-                            (x as f32 + 1.0) * DISTANCE_X_BETWEEN_PIPE,
-                            y_above_pipe,
-                            0.5,
-                        ),
-                        // The z-scale of 2D objects must always be 1.0,
-                        // or their ordering will be affected in surprising ways.
-                        // See https://github.com/bevyengine/bevy/issues/4149
+                        translation: above.extend(0.5),
+                        rotation,
                         scale: Vec3::new(1.0, -1.0, 1.0),
-                        ..default()
                     },
                     ..default()
                 },
                 Direction::Up,
                 ObjectTag::Pipe,
                 Collider,
+                Themed::Pipe,
             ))
             .id();
-        game.pipes.push(Pipe {
-            below: PipePart {
-                entity: Some(bellow),
-            },
-            upper: PipePart {
-                entity: Some(upper),
-            },
-            idx: x + 1,
-        });
-        game.current_inc += 1;
+        pipes.push(x, Pipe { upper, below, sim });
     }
+}
 
-    let texture_handle = asset_server.load("image/bird.png");
-    let texture_atlas =
-        TextureAtlas::from_grid(texture_handle, Vec2::new(36.0, 26.0), 1, 3, None, None);
-    let texture_atlas_handle = texture_atlases.add(texture_atlas);
-    // Use only the subset of sprites in the sheet that make up the run animation
-    let animation_indices = AnimationIndices { first: 0, last: 2 };
-
-    commands.spawn((
-        SpriteSheetBundle {
-            texture_atlas: texture_atlas_handle,
-            sprite: TextureAtlasSprite::new(animation_indices.first),
-            transform: Transform::from_scale(Vec3::splat(1.0)),
-            ..default()
-        },
-        animation_indices,
-        AnimationTimer(Timer::from_seconds(0.1, TimerMode::Repeating)),
-        Bird {
-            speed: 200.0,
-            acc: -5.0,
-            acc_rotation: -60.0,
-            gohell: false,
-        },
-        ObjectTag::Bird,
-        Collider,
-    ));
+fn theme_milestones(game: Res<Game>, mut themes: ResMut<Themes>) {
+    if game.is_changed() {
+        theme::rotate_by_score(game.score, &mut themes);
+    }
+}
 
-    commands.spawn((
-        // Create a TextBundle that has a Text with a single section.
-        TextBundle::from_section(
-            // Accepts a `String` or any type that converts into a `String`, such as `&str`
-            "0",
-            TextStyle {
-                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                font_size: 70.0,
-                color: Color::MIDNIGHT_BLUE,
-            },
-        ) // Set the alignment of the Text
-        .with_text_alignment(TextAlignment::Center)
-        // Set the style of the TextBundle itself.
-        .with_style(Style {
-            position_type: PositionType::Absolute,
+fn skin_milestones(
+    game: Res<Game>,
+    skins: Res<Skins>,
+    mut profile: ResMut<Profile>,
+    autopilot: Res<Autopilot>,
+    options: Res<Options>,
+) {
+    if game.is_changed() && !autopilot.flying() && options.playback.is_none() {
+        skins::unlock_for_score(game.score, &skins, &mut profile);
+    }
+}
 
-            ..default()
-        }),
-        ScoreText,
-    ));
+/// Left and right arrows flip through the unlocked skins before the first flap.
+fn choose_skin(
+    keyboard_input: Res<Input<KeyCode>>,
+    game: Res<Game>,
+    skins: Res<Skins>,
+    mut profile: ResMut<Profile>,
+    mut birds: Query<(&mut Skinned, Option<&Player>)>,
+) {
+    if game.state != 0 {
+        return;
+    }
+    let step = if keyboard_input.just_pressed(KeyCode::Right) {
+        1
+    } else if keyboard_input.just_pressed(KeyCode::Left) {
+        -1
+    } else {
+        return;
+    };
+    let skin = skins.cycle(&profile, step);
+    profile.skin = skin.to_string();
+    profile.save();
+    // The second player keeps their own bird.
+    for (mut skinned, player) in &mut birds {
+        if player.is_none_or(|player| player.index == 0) {
+            skinned.set(skin);
+        }
+    }
 }
 
+/// Scores every bird still flying, ghosts included, by the pipes behind it.
 fn sprite_movement(
-    time: Res<Time>,
-    mut bird_transform: Query<(&mut Bird, &mut Transform), With<Bird>>,
-    mut sprite_position: Query<(&mut ObjectTag, &mut Transform), Without<Bird>>,
+    mut birds: Query<(&mut Bird, &Transform, Option<&Player>)>,
+    pipes: Res<Pool<Pipe>>,
     mut game: ResMut<Game>,
     mut text_query: Query<&mut Text, With<ScoreText>>,
 ) {
-    if game.state == 1 {
-        let mut max_background_x = -1000.0;
-        let mut max_pipe_x: f32 = -1000.0;
-
-        for (object_tag, mut position) in &mut sprite_position {
-            if *object_tag.as_ref() == ObjectTag::Bird {
-                continue;
-            }
-            position.translation.x -= 150.0 * time.delta_seconds();
-            if position.translation.x > max_background_x
-                && *object_tag.as_ref() == ObjectTag::Background
-            {
-                max_background_x = position.translation.x
-            }
-            if position.translation.x > max_pipe_x && *object_tag.as_ref() == ObjectTag::Pipe {
-                max_pipe_x = position.translation.x
+    if game.state != 1 {
+        return;
+    }
+    for (mut bird, transform, player) in &mut birds {
+        if !bird.alive() {
+            continue;
+        }
+        let x = transform.translation.x;
+        bird.score = sim::score_past(bird.score, x, pipes.live().map(|slot| &slot.item.sim));
+        // The big counter follows whichever player is ahead.
+        if player.is_some() && bird.score > game.score {
+            game.score = bird.score;
+            for mut text in &mut text_query {
+                text.sections[0].value = format!("{}", game.score);
             }
         }
+    }
+}
 
-        for (object_tag, mut position) in &mut sprite_position {
-            if position.translation.x < MIN_SCREEN && *object_tag.as_ref() == ObjectTag::Background
-            {
-                position.translation.x = max_background_x + HALF_WIDTH_BACKGROUND;
-            }
+fn flap(bird: &mut Bird, transform: &mut Transform) {
+    bird.speed = FLAP_SPEED;
+    transform.rotation = Quat::from_rotation_z(f32::to_radians(60.0));
+}
+
+fn crash(bird: &mut Bird, transform: &mut Transform) {
+    bird.speed = CRASH_SPEED;
+    transform.rotation = Quat::from_rotation_z(f32::to_radians(-90.0));
+}
+
+/// Applies the flaps input asked for. The first one starts the run.
+fn flap_birds(
+    mut game: ResMut<Game>,
+    mut birds: Query<(&mut Bird, &mut Transform, &Player)>,
+    mut flapped: EventWriter<Flapped>,
+) {
+    for (mut bird, mut transform, player) in &mut birds {
+        if !std::mem::take(&mut bird.flap) || !bird.alive() {
+            continue;
         }
-        let game = &mut *game;
-        for pipe in game.pipes.iter_mut() {
-            if let (Some(upper_entity), Some(below_entity)) = (pipe.upper.entity, pipe.below.entity)
-            {
-                if let Ok(mut upper) = sprite_position.get_mut(upper_entity) {
-                    if upper.1.translation.x < MIN_SCREEN {
-                        let top_below_pipe = rand::thread_rng().gen_range(-100..-50);
-
-                        let y_below_pipe = top_below_pipe as f32 - HEIGHT_PIPE / 2.0;
-                        let y_above_pipe = top_below_pipe as f32
-                            + DISTANCE_BETWEEN_UP_DOWN_PIPES
-                            + HEIGHT_PIPE / 2.0;
-
-                        upper.1.translation.x = max_pipe_x + DISTANCE_X_BETWEEN_PIPE;
-                        upper.1.translation.y = y_above_pipe;
-                        if let Ok(mut below) = sprite_position.get_mut(below_entity) {
-                            below.1.translation.x = max_pipe_x + DISTANCE_X_BETWEEN_PIPE;
-                            below.1.translation.y = y_below_pipe;
-                        }
-                        game.current_inc = game.current_inc + 1;
-                        pipe.idx = game.current_inc;
-                    }
-                }
+        match game.state {
+            0 => {
+                game.state = 1;
+                game.tick = 0;
             }
+            1 => {}
+            _ => continue,
         }
+        flap(&mut bird, &mut transform);
+        flapped.send(Flapped {
+            tick: game.tick,
+            player: player.index,
+        });
+    }
+}
 
-        let (_bird, bird_transform) = bird_transform.single_mut();
-        for pipe in game.pipes.iter_mut() {
-            if let Some(upper_entity) = pipe.upper.entity {
-                if let Ok(upper) = sprite_position.get(upper_entity) {
-                    if upper.1.translation.x < bird_transform.translation.x && game.score < pipe.idx
-                    {
-                        game.score = pipe.idx;
-                        let mut text = text_query.single_mut();
-                        text.sections[0].value = format!("{}", game.score);
-                    }
-                }
-            }
-        }
+fn advance_tick(mut game: ResMut<Game>) {
+    if game.state == 1 {
+        game.tick += 1;
     }
 }
 
 fn bird_movement(
-    time: Res<Time>,
-    mut transforms: Query<(&mut Bird, &mut Transform), With<Bird>>,
+    fixed_time: Res<FixedTime>,
+    mut transforms: Query<(
+        &mut Bird,
+        &mut Transform,
+        &Hitbox,
+        Option<&Ghost>,
+        Option<&Player>,
+    )>,
     mut game: ResMut<Game>,
 ) {
-    let (mut bird, mut transform) = transforms.single_mut();
-    if (game.state == 1 || game.state == 2) && !bird.gohell {
-        bird.speed += bird.acc;
-        transform.translation.y += bird.speed * time.delta_seconds();
-        transform.rotate_z(f32::to_radians(bird.acc_rotation * time.delta_seconds()));
+    let dt = fixed_time.period.as_secs_f32();
+    for (mut bird, mut transform, hitbox, ghost, _) in &mut transforms {
+        // A ghost only moves while the world scrolls under it.
+        let moving = match ghost {
+            Some(_) => game.state == 1,
+            None => game.state == 1 || game.state == 2,
+        };
+        if !moving || bird.gohell {
+            continue;
+        }
+        let acc = bird.acc;
+        let landing = sim::fall(
+            &mut transform.translation.y,
+            &mut bird.speed,
+            acc,
+            hitbox.y / 2.0,
+            dt,
+        );
+        transform.rotate_z(f32::to_radians(bird.acc_rotation * dt));
         if transform.rotation.z <= f32::to_radians(-90.0) {
             transform.rotation.z = f32::to_radians(-90.0);
         }
-        if transform.translation.y + BIRTH_HEIGHT / 2.0 >= HEIGHT_SCREEN / 2.0 - GROUND_HEIGHT {
-            transform.translation.y = HEIGHT_SCREEN / 2.0 - GROUND_HEIGHT - BIRTH_HEIGHT / 2.0;
-            bird.speed = -3.0;
+        bird.ceiling = landing == Landing::Ceiling;
+        if bird.ceiling {
             transform.rotate_z(f32::to_radians(60.0));
         }
 
-        if transform.translation.y - BIRTH_HEIGHT / 2.0 <= -HEIGHT_SCREEN / 2.0 + GROUND_HEIGHT {
+        if landing == Landing::Ground {
+            if bird.alive() {
+                bird.cause = Some(Cause::Ground);
+            }
             bird.gohell = true;
-            game.state = 3;
+            bird.down_at.get_or_insert(game.tick);
         }
         //println!("bird transform: {:?}, {:?}",bird, transform.rotation.to_euler(EulerRot::XYZ));
     }
+
+    // The run is over once every player is down, and the world waits for the
+    // last of them to reach the ground.
+    if game.state == 1 || game.state == 2 {
+        let players = transforms
+            .iter()
+            .filter(|(_, _, _, _, player)| player.is_some());
+        let (mut down, mut grounded, mut count) = (0, 0, 0);
+        for (bird, ..) in players {
+            count += 1;
+            down += !bird.alive() as usize;
+            grounded += bird.gohell as usize;
+        }
+        if count > 0 && down == count {
+            game.state = if grounded == count { 3 } else { 2 };
+        }
+    }
 }
 
+/// Checks the players' birds against the pipes the way the simulation does.
+/// A hit knocks a bird out unless a shield takes it.
 fn check_for_collisions(
-    mut game: ResMut<Game>,
-    mut bird_query: Query<(&mut Bird, &mut Transform), With<Bird>>,
-    collider_query: Query<(Entity, &Transform), (With<Collider>, Without<Bird>)>,
-    mut collision_events: EventWriter<CollisionEvent>,
+    game: Res<Game>,
+    mut effects: ResMut<ActiveEffects>,
+    mut bird_query: Query<(&mut Bird, &mut Transform, &Hitbox), Without<Ghost>>,
+    pipes: Res<Pool<Pipe>>,
 ) {
-    let (mut ball_velocity, mut ball_transform) = bird_query.single_mut();
-
-    // check collision with walls
-    for (_collider_entity, transform) in &collider_query {
-        let collision = collide(
-            ball_transform.translation,
-            Vec2::new(26.0, 26.0),
-            transform.translation,
-            Vec2::new(54.0, 319.0),
-        );
-        if let Some(_collision) = collision {
-            // Sends a collision event so that other systems can react to the collision
-            collision_events.send_default();
-            ball_velocity.speed = -300.0;
-            ball_transform.rotation = Quat::from_rotation_z(f32::to_radians(-90.0));
-            game.state = 2;
-            break;
+    if game.state != 1 {
+        return;
+    }
+    for (mut bird, mut transform, hitbox) in &mut bird_query {
+        if !bird.alive() {
+            continue;
+        }
+        let centre = transform.translation.truncate();
+        let Some(cause) = pipes
+            .live()
+            .find_map(|slot| slot.item.sim.hit(centre, hitbox.0))
+        else {
+            continue;
+        };
+        if powerups::absorb_hit(&mut effects) {
+            continue;
         }
+        crash(&mut bird, &mut transform);
+        bird.down_at = Some(game.tick);
+        // A hit while pressed against the top is the ceiling's fault.
+        bird.cause = Some(if bird.ceiling { Cause::Ceiling } else { cause });
     }
 }
 
@@ -459,18 +700,217 @@ fn touch_system(touches: Res<Touches>) {
     }
 }
 
+/// Each player flaps with their own key; the mouse flies the first player.
 fn mouse_click_system(
     mouse_button_input: Res<Input<MouseButton>>,
-    mut transforms: Query<(&mut Bird, &mut Transform), With<Bird>>,
-    mut game: ResMut<Game>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut birds: Query<(&mut Bird, &Player)>,
+    game: Res<Game>,
 ) {
-    let (mut bird, mut transform) = transforms.single_mut();
-    if (game.state == 0 || game.state == 1) && !bird.gohell {
-        if mouse_button_input.pressed(MouseButton::Left) {
-            bird.speed = 200.0;
-            transform.rotation = Quat::from_rotation_z(f32::to_radians(60.0));
-            game.state = 1;
-            //println!("bird click: {:?}",f32::to_radians(60.0));
+    if game.state != 0 && game.state != 1 {
+        return;
+    }
+    for (mut bird, player) in &mut birds {
+        let mouse = player.index == 0 && mouse_button_input.pressed(MouseButton::Left);
+        if mouse || keyboard_input.pressed(player.key) {
+            bird.flap = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use bevy::ecs::event::ManualEventReader;
+    use bevy::time::TimeUpdateStrategy;
+    use my_bevy_game::course::Difficulty;
+    use my_bevy_game::env::{Action, Env};
+    use my_bevy_game::pilot::{Pilot, Skill};
+    use my_bevy_game::replay::Replay;
+    use my_bevy_game::sim::{Race, BIRD_SIZE, TICK_SECS};
+
+    use super::*;
+    use crate::autopilot::Autopilot;
+    use crate::ghost::RunRecorded;
+    use crate::powerups::PowerUps;
+
+    /// Keeps what the game saves out of the real data and config folders.
+    fn isolate_saves() {
+        static ONCE: std::sync::Once = std::sync::Once::new();
+        ONCE.call_once(|| {
+            let dir = std::env::temp_dir().join(format!("flappy-bevy-{}", std::process::id()));
+            std::env::set_var("HOME", &dir);
+            std::env::set_var("XDG_DATA_HOME", dir.join("data"));
+            std::env::set_var("XDG_CONFIG_HOME", dir.join("config"));
+        });
+    }
+
+    /// A run the autopilot flies on the simulated course.
+    fn flown(seed: u64, difficulty: Difficulty, skill: Skill) -> Replay {
+        let mut env = Env::new(difficulty);
+        let mut observation = env.reset(seed);
+        let mut pilot = Pilot::new(skill, seed);
+        let mut flaps = Vec::new();
+        while !env.done() {
+            // The game's runs start with a flap.
+            let action = if flaps.is_empty() {
+                Action::Flap
+            } else {
+                pilot.act(observation)
+            };
+            if action == Action::Flap {
+                flaps.push(env.tick());
+            }
+            observation = env.step(action).0;
+        }
+        Replay {
+            seed,
+            difficulty,
+            flaps,
+            end: env.race().birds[0].down_at.expect("the bird is down"),
+            score: env.score(),
+            hitbox: (BIRD_SIZE.x, BIRD_SIZE.y),
+            shielded: None,
         }
     }
+
+    /// Plays `replay` back in the headless game, one tick a frame, and returns
+    /// the tick the bird went down on and its score.
+    fn play(replay: &Replay, tweak: impl FnOnce(&mut App)) -> (u32, i32) {
+        isolate_saves();
+        let options = Options {
+            headless: true,
+            playback: Some(replay.clone()),
+            ..default()
+        };
+        let mut app = game(options, SettingsPlugin::load());
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+            TICK_SECS,
+        )));
+        tweak(&mut app);
+        app.setup();
+        // The art loads on other threads, so allow it time rather than frames.
+        let deadline = Instant::now() + Duration::from_secs(120);
+        while app.world.resource::<Game>().state != 3 && Instant::now() < deadline {
+            app.update();
+        }
+        assert_eq!(app.world.resource::<Game>().state, 3, "the run never ended");
+        let mut players = app.world.query::<(&Bird, &Player)>();
+        let (bird, _) = players
+            .iter(&app.world)
+            .find(|(_, player)| player.index == 0)
+            .expect("the first player's bird");
+        (bird.down_at.expect("the bird is down"), bird.score)
+    }
+
+    /// Flies a run in the headless game, flapping whenever the bird sinks
+    /// below the middle, and returns what the game recorded of it.
+    fn record(seed: u64) -> (Replay, Cause) {
+        isolate_saves();
+        let options = Options {
+            headless: true,
+            seed: Some(seed),
+            ..default()
+        };
+        let mut app = game(options, SettingsPlugin::load());
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+            TICK_SECS,
+        )));
+        app.world.resource_mut::<PowerUps>().chance = 0.0;
+        app.setup();
+        let mut reader = ManualEventReader::<RunRecorded>::default();
+        let mut players = app.world.query::<(&mut Bird, &Transform, &Player)>();
+        let deadline = Instant::now() + Duration::from_secs(120);
+        while Instant::now() < deadline {
+            let title = app.world.resource::<Game>().state == 0;
+            let laid_out = app.world.resource::<Pool<Pipe>>().live().next().is_some();
+            if title {
+                // Keeps the demo from taking over the title screen.
+                *app.world.resource_mut::<Autopilot>() = Autopilot::default();
+            }
+            for (mut bird, transform, player) in players.iter_mut(&mut app.world) {
+                let sinking = !title && transform.translation.y < 0.0;
+                if player.index == 0 && bird.alive() && laid_out && (title || sinking) {
+                    bird.flap = true;
+                }
+            }
+            app.update();
+            let events = app.world.resource::<Events<RunRecorded>>();
+            if let Some(RunRecorded { replay }) = reader.iter(events).next() {
+                let (bird, ..) = players
+                    .iter(&app.world)
+                    .find(|(.., player)| player.index == 0)
+                    .expect("the first player's bird");
+                return (replay.clone(), bird.cause.expect("the bird is down"));
+            }
+        }
+        panic!("the run was never recorded");
+    }
+
+    #[test]
+    fn a_recorded_run_holds_up() {
+        let (replay, cause) = record(5);
+        assert!(
+            matches!(cause, Cause::UpperPipe | Cause::LowerPipe),
+            "the run should end on a pipe, not {cause:?}"
+        );
+        let race = replay.verify().expect("the recording verifies");
+        assert_eq!(race.birds[0].down_at, Some(replay.end));
+    }
+
+    #[test]
+    fn a_replay_plays_back_the_same_every_time() {
+        let replay = flown(3, Difficulty::Normal, Skill::Novice);
+        // Power-ups on every spot, so any luck in them would show.
+        let every_spot = |app: &mut App| {
+            app.world.resource_mut::<PowerUps>().chance = 1.0;
+        };
+        let first = play(&replay, every_spot);
+        let second = play(&replay, every_spot);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn the_game_flies_a_run_the_way_the_simulation_does() {
+        let no_power_ups = |app: &mut App| {
+            app.world.resource_mut::<PowerUps>().chance = 0.0;
+        };
+        // Hard courses have moving pipes from the start.
+        for seed in [1, 2] {
+            let replay = flown(seed, Difficulty::Hard, Skill::Average);
+            let race = replay.verify().expect("the simulation accepts its own run");
+            let bird = &race.birds[0];
+            let simulated = (bird.down_at.unwrap(), bird.score);
+            assert_eq!(play(&replay, no_power_ups), simulated, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn a_replay_is_flown_with_the_hitbox_it_was_recorded_with() {
+        let mut replay = flown(1, Difficulty::Normal, Skill::Average);
+        replay.hitbox = (40.0, 40.0);
+        let mut race = Race::new(replay.seed, replay.difficulty);
+        race.add_sized_bird(0.0, Vec2::from(replay.hitbox));
+        let mut flaps = replay.flaps.iter().peekable();
+        while race.birds[0].alive() {
+            while flaps.next_if(|&&tick| tick <= race.tick).is_some() {
+                race.flap(0);
+            }
+            race.step();
+        }
+        let bird = &race.birds[0];
+        assert_ne!(
+            bird.down_at,
+            Some(replay.end),
+            "the bigger bird crashes sooner"
+        );
+        let no_power_ups = |app: &mut App| {
+            app.world.resource_mut::<PowerUps>().chance = 0.0;
+        };
+        assert_eq!(
+            play(&replay, no_power_ups),
+            (bird.down_at.unwrap(), bird.score)
+        );
+    }
 }
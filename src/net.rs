@@ -0,0 +1,83 @@
+//! Messages between the race server and the game. Every datagram carries one
+//! message written as RON.
+
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::course::Difficulty;
+
+/// Bumped whenever a message changes shape.
+pub const PROTOCOL: u32 = 1;
+pub const DEFAULT_ADDR: &str = "127.0.0.1:7878";
+/// Most players one race takes.
+pub const MAX_PLAYERS: usize = 8;
+/// How long either side waits to hear anything before giving up on the other.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+/// How often clients say they are still there when they have nothing else to say.
+pub const PING_EVERY: Duration = Duration::from_secs(1);
+/// Ticks the server rewinds at most to apply a late flap.
+pub const MAX_REWIND: u32 = 30;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ClientMessage {
+    Join {
+        protocol: u32,
+        name: String,
+    },
+    /// The player wants the next race to start.
+    Ready,
+    /// The player flapped on `tick` of the race as the client saw it.
+    Flap {
+        tick: u32,
+    },
+    Ping,
+    Leave,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ServerMessage {
+    Welcome {
+        id: u32,
+    },
+    Refused {
+        reason: String,
+    },
+    /// A race starts now on this course, with these players.
+    Start {
+        seed: u64,
+        difficulty: Difficulty,
+        players: Vec<(u32, String)>,
+    },
+    State {
+        tick: u32,
+        birds: Vec<BirdState>,
+    },
+    /// Final scores, best first.
+    Results {
+        scores: Vec<(String, i32)>,
+    },
+    Left {
+        id: u32,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BirdState {
+    pub id: u32,
+    pub y: f32,
+    pub speed: f32,
+    pub score: i32,
+    pub down: bool,
+}
+
+pub fn encode<T: Serialize>(message: &T) -> Vec<u8> {
+    ron::ser::to_string(message)
+        .expect("messages always serialize")
+        .into_bytes()
+}
+
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+    let text = std::str::from_utf8(bytes).map_err(|err| err.to_string())?;
+    ron::from_str(text).map_err(|err| err.to_string())
+}
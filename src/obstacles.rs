@@ -0,0 +1,68 @@
+use bevy::prelude::*;
+
+use my_bevy_game::course::pipe_parts;
+pub use my_bevy_game::course::{Behaviour, Difficulty, PipeGenerator, PipeSpec};
+
+use crate::cli::Options;
+use crate::levels::CourseReset;
+use crate::pool::{self, Pool};
+use crate::{bird_movement, is_playing, Game, Pipe, DISTANCE_X_BETWEEN_PIPE};
+
+pub struct ObstaclesPlugin;
+
+impl Plugin for ObstaclesPlugin {
+    fn build(&self, app: &mut App) {
+        let options = app.world.resource::<Options>();
+        let difficulty = options.difficulty.unwrap_or(Difficulty::Normal);
+        let seed = options.seed.unwrap_or_else(rand::random);
+        app.insert_resource(PipeGenerator::new(difficulty, seed))
+            .add_system(cycle_difficulty)
+            .add_system(
+                move_pipes
+                    .after(pool::scroll::<Pipe>)
+                    .before(bird_movement)
+                    .run_if(is_playing)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+/// F6 steps through the difficulties before the first flap, laying the
+/// course out again so the pipes already shown follow the new one.
+fn cycle_difficulty(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut game: ResMut<Game>,
+    mut course: CourseReset,
+) {
+    if game.state == 0 && keyboard_input.just_pressed(KeyCode::F6) {
+        let generator = course.generator();
+        generator.difficulty = match generator.difficulty {
+            Difficulty::Easy => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Easy,
+        };
+        info!("difficulty {:?}", generator.difficulty);
+        course.reset(&mut game, 1, DISTANCE_X_BETWEEN_PIPE);
+    }
+}
+
+/// Places both parts of every pair around the current centre of its
+/// opening, as far through its motion as the simulation has it where it is.
+/// Runs after the pool has scrolled them, so x includes the swing, and before
+/// the birds move, so collisions see where the pipes are this tick.
+fn move_pipes(mut pipes: ResMut<Pool<Pipe>>, mut transforms: Query<&mut Transform>) {
+    for slot in pipes.live_mut() {
+        let pipe = &mut slot.item;
+        pipe.sim.place(slot.x);
+        let sim = &pipe.sim;
+        let parts = pipe_parts(Vec2::new(sim.x, sim.gap), sim.dh, sim.angle);
+        let rotation = Quat::from_rotation_z(sim.angle);
+        for (entity, centre) in [pipe.upper, pipe.below].into_iter().zip(parts) {
+            if let Ok(mut transform) = transforms.get_mut(entity) {
+                transform.translation.x = centre.x;
+                transform.translation.y = centre.y;
+                transform.rotation = rotation;
+            }
+        }
+    }
+}
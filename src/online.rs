@@ -0,0 +1,364 @@
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Instant;
+
+use bevy::{app::AppExit, prelude::*};
+use my_bevy_game::net::{self, ClientMessage, ServerMessage, PING_EVERY, PROTOCOL, TIMEOUT};
+
+use crate::cli::Options;
+use crate::ghost::Ghost;
+use crate::levels::{CourseReset, SelectedLevel};
+use crate::powerups::ActiveEffects;
+use crate::profile::Profile;
+use crate::settings::menu_closed;
+use crate::skins::{Skinned, Skins};
+use crate::{
+    crash, flap_birds, spawn_pipes, Bird, Flapped, Game, Player, ScoreText, DISTANCE_X_BETWEEN_PIPE,
+};
+
+/// The link to a race server, when the game was started with
+/// `--connect <address>`. Our bird is flown here as usual and the server has
+/// the last word on when it goes down; everyone else's bird follows what the
+/// server reports.
+#[derive(Resource)]
+pub struct Online {
+    socket: UdpSocket,
+    server: SocketAddr,
+    name: String,
+    id: Option<u32>,
+    last_heard: Instant,
+    last_sent: Instant,
+    status: String,
+    results: Vec<(String, i32)>,
+}
+
+impl Online {
+    fn connect(server: &str, name: String) -> Result<Self, String> {
+        let server = server
+            .to_socket_addrs()
+            .map_err(|err| err.to_string())?
+            .next()
+            .ok_or_else(|| format!("{server} has no address"))?;
+        let local = if server.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local).map_err(|err| err.to_string())?;
+        socket
+            .set_nonblocking(true)
+            .map_err(|err| err.to_string())?;
+        let mut online = Online {
+            socket,
+            server,
+            name,
+            id: None,
+            last_heard: Instant::now(),
+            last_sent: Instant::now(),
+            status: format!("connecting to {server}"),
+            results: Vec::new(),
+        };
+        online.join();
+        Ok(online)
+    }
+
+    fn send(&mut self, message: &ClientMessage) {
+        if let Err(err) = self.socket.send_to(&net::encode(message), self.server) {
+            warn!("could not send to {}: {err}", self.server);
+        }
+        self.last_sent = Instant::now();
+    }
+
+    fn join(&mut self) {
+        let message = ClientMessage::Join {
+            protocol: PROTOCOL,
+            name: self.name.clone(),
+        };
+        self.send(&message);
+    }
+
+    fn receive(&mut self) -> Option<ServerMessage> {
+        let mut buf = [0; 1500];
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((len, from)) if from == self.server => match net::decode(&buf[..len]) {
+                    Ok(message) => {
+                        self.last_heard = Instant::now();
+                        return Some(message);
+                    }
+                    Err(err) => warn!("ignoring bad message from the server: {err}"),
+                },
+                Ok(_) => {}
+                // Raised by some platforms while the server is not up yet.
+                Err(err) if err.kind() == std::io::ErrorKind::ConnectionReset => {}
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+/// Another player's bird as the server last reported it.
+#[derive(Component)]
+struct Remote {
+    id: u32,
+    y: f32,
+    speed: f32,
+}
+
+#[derive(Component)]
+struct OnlineText;
+
+pub struct OnlinePlugin;
+
+impl Plugin for OnlinePlugin {
+    fn build(&self, app: &mut App) {
+        let options = app.world.resource::<Options>();
+        let Some(server) = options.connect.clone() else {
+            return;
+        };
+        let name = options
+            .name
+            .clone()
+            .or_else(|| std::env::var("USER").ok())
+            .unwrap_or_else(|| "player".to_string());
+        match Online::connect(&server, name) {
+            Ok(online) => {
+                app.insert_resource(online);
+            }
+            Err(err) => {
+                error!("could not connect to {server}: {err}");
+                return;
+            }
+        }
+        app.add_startup_system(spawn_online_text)
+            .add_system(receive.before(spawn_pipes))
+            .add_system(
+                hold_start
+                    .before(flap_birds)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(send_flaps)
+            .add_system(send_ready.run_if(menu_closed))
+            .add_system(keep_alive)
+            .add_system(follow_remotes)
+            .add_system(leave_on_exit.in_base_set(CoreSet::Last))
+            .add_system(update_online_text);
+    }
+}
+
+fn receive(
+    mut commands: Commands,
+    mut online: ResMut<Online>,
+    mut game: ResMut<Game>,
+    mut selected: ResMut<SelectedLevel>,
+    mut effects: ResMut<ActiveEffects>,
+    mut course: CourseReset,
+    skins: Res<Skins>,
+    profile: Res<Profile>,
+    mut players: Query<(&mut Bird, &mut Transform, &mut Visibility), With<Player>>,
+    mut ghosts: Query<&mut Visibility, (With<Ghost>, Without<Player>)>,
+    mut remotes: Query<(Entity, &mut Remote)>,
+    mut score_text: Query<&mut Text, With<ScoreText>>,
+) {
+    while let Some(message) = online.receive() {
+        match message {
+            ServerMessage::Welcome { id } => {
+                if online.id.is_none() {
+                    info!("joined the server as {id}");
+                }
+                online.id = Some(id);
+                online.status = "connected, Enter when ready".to_string();
+            }
+            ServerMessage::Refused { reason } => {
+                warn!("the server refused us: {reason}");
+                online.status = reason;
+            }
+            ServerMessage::Start {
+                seed,
+                difficulty,
+                players: racers,
+            } => {
+                let generator = course.generator();
+                generator.difficulty = difficulty;
+                generator.reseed(seed);
+                selected.0 = None;
+                course.reset(&mut game, 1, DISTANCE_X_BETWEEN_PIPE);
+                game.state = 1;
+                *effects = ActiveEffects::default();
+                for (mut bird, mut transform, mut visibility) in &mut players {
+                    *bird = Bird::default();
+                    *transform = Transform::from_xyz(transform.translation.x, 0.0, 0.0);
+                    *visibility = Visibility::Inherited;
+                }
+                for mut visibility in &mut ghosts {
+                    *visibility = Visibility::Hidden;
+                }
+                for mut text in &mut score_text {
+                    text.sections[0].value = "0".to_string();
+                }
+                for (entity, _) in &remotes {
+                    commands.entity(entity).despawn_recursive();
+                }
+                let others = racers.iter().filter(|(id, _)| Some(*id) != online.id);
+                for (step, (id, _)) in others.enumerate() {
+                    commands.spawn((
+                        SpriteSheetBundle {
+                            transform: Transform::from_xyz(0.0, 0.0, -0.1),
+                            ..default()
+                        },
+                        Skinned::new(skins.cycle(&profile, step as isize + 1)),
+                        Remote {
+                            id: *id,
+                            y: 0.0,
+                            speed: 0.0,
+                        },
+                    ));
+                }
+                online.results.clear();
+                online.status = format!("racing {} players", racers.len());
+            }
+            ServerMessage::State { birds, .. } => {
+                for state in birds {
+                    if Some(state.id) == online.id {
+                        // The server saw us hit something we slipped past here.
+                        if state.down && game.state == 1 {
+                            for (mut bird, mut transform, _) in &mut players {
+                                if bird.alive() {
+                                    crash(&mut bird, &mut transform);
+                                    bird.down_at = Some(game.tick);
+                                }
+                            }
+                        }
+                        continue;
+                    }
+                    for (_, mut remote) in &mut remotes {
+                        if remote.id == state.id {
+                            remote.y = state.y;
+                            remote.speed = state.speed;
+                        }
+                    }
+                }
+            }
+            ServerMessage::Results { scores } => {
+                online.results = scores;
+                online.status = "race over, Enter when ready".to_string();
+            }
+            ServerMessage::Left { id } => {
+                for (entity, remote) in &remotes {
+                    if remote.id == id {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Runs start together when the server says so, not on our first flap.
+fn hold_start(game: Res<Game>, mut players: Query<&mut Bird, With<Player>>) {
+    if game.state == 0 {
+        for mut bird in &mut players {
+            bird.flap = false;
+        }
+    }
+}
+
+/// Flaps go out stamped with the tick we saw them on, so the server can play
+/// them back where they happened for us however late they arrive.
+fn send_flaps(mut online: ResMut<Online>, mut flapped: EventReader<Flapped>) {
+    for event in flapped.iter() {
+        online.send(&ClientMessage::Flap { tick: event.tick });
+    }
+}
+
+fn send_ready(keyboard_input: Res<Input<KeyCode>>, game: Res<Game>, mut online: ResMut<Online>) {
+    if online.id.is_none() || !matches!(game.state, 0 | 3) {
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        online.send(&ClientMessage::Ready);
+        online.status = "ready, waiting for the others".to_string();
+    }
+}
+
+/// Pings the server when there is nothing else to send, and starts joining
+/// again once it has been quiet for too long.
+fn keep_alive(
+    mut commands: Commands,
+    mut online: ResMut<Online>,
+    remotes: Query<Entity, With<Remote>>,
+) {
+    if online.id.is_some() && online.last_heard.elapsed() > TIMEOUT {
+        warn!("lost the connection to {}", online.server);
+        online.id = None;
+        online.status = format!("lost {}, reconnecting", online.server);
+        for entity in &remotes {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    if online.last_sent.elapsed() < PING_EVERY {
+        return;
+    }
+    if online.id.is_some() {
+        online.send(&ClientMessage::Ping);
+    } else {
+        online.join();
+    }
+}
+
+/// Eases the other birds towards where the server last put them.
+fn follow_remotes(
+    time: Res<Time>,
+    mut remotes: Query<(&Remote, &mut Transform, &mut TextureAtlasSprite)>,
+) {
+    let blend = (time.delta_seconds() * 20.0).min(1.0);
+    for (remote, mut transform, mut sprite) in &mut remotes {
+        transform.translation.y += (remote.y - transform.translation.y) * blend;
+        let angle = (remote.speed / 200.0 * 60.0).clamp(-90.0, 60.0);
+        transform.rotation = Quat::from_rotation_z(angle.to_radians());
+        if sprite.color.a() != 0.6 {
+            sprite.color.set_a(0.6);
+        }
+    }
+}
+
+fn leave_on_exit(mut exits: EventReader<AppExit>, mut online: ResMut<Online>) {
+    if exits.iter().next().is_some() && online.id.is_some() {
+        online.send(&ClientMessage::Leave);
+    }
+}
+
+fn spawn_online_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 24.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+        OnlineText,
+    ));
+}
+
+fn update_online_text(online: Res<Online>, mut text: Query<&mut Text, With<OnlineText>>) {
+    if !online.is_changed() {
+        return;
+    }
+    let mut value = online.status.clone();
+    for (place, (name, score)) in online.results.iter().enumerate() {
+        value.push_str(&format!("\n{}. {name} {score}", place + 1));
+    }
+    for mut text in &mut text {
+        text.sections[0].value = value.clone();
+    }
+}
@@ -0,0 +1,182 @@
+use bevy::prelude::*;
+
+use crate::atlas::{Art, ArtLoader};
+use crate::theme::Themed;
+
+/// One horizontally repeating strip of scenery. `speed` is a factor of the
+/// world scroll speed, so 1.0 moves with the pipes and smaller values appear
+/// further away.
+pub struct ParallaxLayer {
+    pub art: Art,
+    pub color: Color,
+    pub speed: f32,
+    pub z: f32,
+    pub y: f32,
+    pub flip_y: bool,
+    pub themed: Option<Themed>,
+}
+
+#[derive(Resource)]
+pub struct Parallax {
+    pub speed: f32,
+    pub view_width: f32,
+    /// How long a layer takes to fade in when its art or colour changes.
+    pub fade_secs: f32,
+    pub layers: Vec<ParallaxLayer>,
+}
+
+#[derive(Component)]
+pub struct Layer {
+    index: usize,
+    art: Art,
+    color: Color,
+    width: Option<f32>,
+    tiles: usize,
+    offset: f32,
+    alpha: f32,
+    retired: bool,
+}
+
+/// Spawns a layer for every description that has none yet. When the art or
+/// colour of a description changes, the old layer is retired behind a new one
+/// that fades in on top of it.
+pub fn sync_layers(
+    mut commands: Commands,
+    parallax: Res<Parallax>,
+    mut layers: Query<(&mut Layer, &mut Transform)>,
+) {
+    for (index, desc) in parallax.layers.iter().enumerate() {
+        let mut current = layers
+            .iter_mut()
+            .find(|(layer, _)| layer.index == index && !layer.retired);
+        let mut offset = 0.0;
+        let mut alpha = 1.0;
+        if let Some((layer, transform)) = &mut current {
+            if layer.art == desc.art && layer.color == desc.color {
+                continue;
+            }
+            layer.retired = true;
+            transform.translation.z = desc.z - 0.01;
+            offset = layer.offset;
+            alpha = 0.0;
+        }
+        commands.spawn((
+            SpatialBundle::from_transform(Transform::from_xyz(-offset, desc.y, desc.z)),
+            Layer {
+                index,
+                art: desc.art,
+                color: desc.color,
+                width: None,
+                tiles: 0,
+                offset,
+                alpha,
+                retired: false,
+            },
+        ));
+    }
+}
+
+/// Fills each layer with as many copies of its art as it takes to cover the
+/// view plus one spare, once the art is loaded and its width is known, and
+/// again whenever the view width changes.
+pub fn tile_layers(
+    mut commands: Commands,
+    art: ArtLoader,
+    parallax: Res<Parallax>,
+    mut layers: Query<(Entity, &mut Layer)>,
+) {
+    for (entity, mut layer) in &mut layers {
+        if layer.width.is_some() && !parallax.is_changed() {
+            continue;
+        }
+        let Some(resolved) = art.resolve(layer.art) else {
+            continue;
+        };
+        let Some(desc) = parallax.layers.get(layer.index) else {
+            continue;
+        };
+        let width = resolved.size.x;
+        let count = (parallax.view_width / width).ceil() as usize + 1;
+        if layer.width == Some(width) && layer.tiles == count {
+            continue;
+        }
+        let left = -parallax.view_width / 2.0 + width / 2.0;
+        let mut color = layer.color;
+        color.set_a(layer.alpha);
+        commands.entity(entity).despawn_descendants();
+        commands.entity(entity).with_children(|parent| {
+            for i in 0..count {
+                let mut tile = parent.spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color,
+                        flip_y: desc.flip_y,
+                        rect: resolved.rect,
+                        ..default()
+                    },
+                    texture: resolved.texture.clone(),
+                    transform: Transform::from_xyz(left + i as f32 * width, 0.0, 0.0),
+                    ..default()
+                });
+                if let Some(themed) = desc.themed {
+                    tile.insert(themed);
+                }
+            }
+        });
+        layer.width = Some(width);
+        layer.tiles = count;
+    }
+}
+
+pub fn fade_layers(
+    mut commands: Commands,
+    time: Res<Time>,
+    parallax: Res<Parallax>,
+    mut layers: Query<(Entity, &mut Layer, Option<&Children>)>,
+    mut sprites: Query<&mut Sprite>,
+) {
+    let step = time.delta_seconds() / parallax.fade_secs.max(f32::EPSILON);
+    let incoming: Vec<usize> = layers
+        .iter()
+        .filter(|(_, layer, _)| !layer.retired && layer.alpha < 1.0)
+        .map(|(_, layer, _)| layer.index)
+        .collect();
+    for (entity, mut layer, children) in &mut layers {
+        if layer.retired {
+            // Stay opaque underneath until the replacement is fully visible.
+            if !incoming.contains(&layer.index) {
+                commands.entity(entity).despawn_recursive();
+            }
+            continue;
+        }
+        let Some(children) = children else {
+            continue;
+        };
+        if layer.alpha >= 1.0 {
+            continue;
+        }
+        layer.alpha = (layer.alpha + step).min(1.0);
+        let mut tiles = sprites.iter_many_mut(children);
+        while let Some(mut sprite) = tiles.fetch_next() {
+            sprite.color.set_a(layer.alpha);
+        }
+    }
+}
+
+pub fn scroll(
+    fixed_time: Res<FixedTime>,
+    parallax: Res<Parallax>,
+    mut layers: Query<(&mut Layer, &mut Transform)>,
+) {
+    for (mut layer, mut transform) in &mut layers {
+        let Some(width) = layer.width else {
+            continue;
+        };
+        let speed = parallax
+            .layers
+            .get(layer.index)
+            .map_or(0.0, |desc| desc.speed);
+        layer.offset =
+            (layer.offset + parallax.speed * speed * fixed_time.period.as_secs_f32()) % width;
+        transform.translation.x = -layer.offset;
+    }
+}
@@ -0,0 +1,207 @@
+//! A bird that flies itself. It aims for the middle of the next opening and
+//! flaps whenever gravity would carry it too low within a tick. Lower
+//! skills see the world late and aim less precisely, like people do.
+
+use std::collections::VecDeque;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::env::{Action, Observation};
+use crate::sim::{Rules, BIRD_SIZE, TICK_SECS};
+use crate::{SCROLL_SPEED, WIDTH_PIPE};
+
+/// Room kept between the bird and the edge of an opening.
+const MARGIN: f32 = 8.0;
+/// Most ticks ahead the pilot guesses where a moving opening will be.
+const LOOK_AHEAD: f32 = 20.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Skill {
+    /// Reacts on the tick it sees something and aims for the very middle. It
+    /// still only guesses how a moving opening moves, so it does crash.
+    #[serde(alias = "Perfect")]
+    Master,
+    Expert,
+    Average,
+    Novice,
+}
+
+impl Skill {
+    /// Ticks between seeing something and acting on it, and how many units
+    /// the aim may be off by.
+    fn limits(&self) -> (usize, f32) {
+        match self {
+            Skill::Master => (0, 0.0),
+            Skill::Expert => (2, 6.0),
+            Skill::Average => (5, 14.0),
+            Skill::Novice => (8, 24.0),
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Skill::Master => Skill::Expert,
+            Skill::Expert => Skill::Average,
+            Skill::Average => Skill::Novice,
+            Skill::Novice => Skill::Master,
+        }
+    }
+}
+
+pub struct Pilot {
+    pub skill: Skill,
+    rng: StdRng,
+    /// What the pilot saw over the last few ticks, oldest first.
+    seen: VecDeque<Observation>,
+    /// How far off the middle of the current opening the pilot aims.
+    aim: f32,
+    /// The physics the pilot expects.
+    rules: Rules,
+}
+
+impl Pilot {
+    pub fn new(skill: Skill, seed: u64) -> Self {
+        Pilot {
+            skill,
+            rng: StdRng::seed_from_u64(seed),
+            seen: VecDeque::new(),
+            aim: 0.0,
+            rules: Rules::default(),
+        }
+    }
+
+    /// Flies races with other physics than the game's.
+    pub fn with_rules(mut self, rules: Rules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Forgets what it saw, e.g. when a new run starts.
+    pub fn clear(&mut self) {
+        self.seen.clear();
+    }
+
+    /// Decides what to do this tick from what the bird sees now.
+    pub fn act(&mut self, observation: Observation) -> Action {
+        let (delay, error) = self.skill.limits();
+        // A new opening to aim for: pick a spot once, like a person would.
+        let new_gap = self
+            .seen
+            .back()
+            .is_none_or(|last| observation.gap_distance > last.gap_distance);
+        if new_gap {
+            self.aim = if error > 0.0 {
+                self.rng.gen_range(-error..error)
+            } else {
+                0.0
+            };
+        }
+        self.seen.push_back(observation);
+        // One look more than the delay, to tell how the opening moves.
+        while self.seen.len() > delay + 2 {
+            self.seen.pop_front();
+        }
+        let now = self.seen.len().saturating_sub(delay + 1);
+        let seen = self.seen[now];
+        let before = now.checked_sub(1).map(|i| self.seen[i]);
+        let lowest = if seen.gap_bottom.is_finite() {
+            lowest_flap(seen, before, self.rules)
+        } else {
+            // Nothing ahead yet; hold the middle of the screen.
+            0.0
+        };
+        let bottom = lowest + self.aim;
+        // Where the bird will be next tick if it does nothing.
+        let speed = seen.velocity + self.rules.gravity;
+        let next_y = seen.bird_y + speed * TICK_SECS;
+        if next_y < bottom {
+            Action::Flap
+        } else {
+            Action::NoOp
+        }
+    }
+}
+
+/// How high a flap carries a bird before it starts falling again.
+fn flap_rise(rules: Rules) -> f32 {
+    // A bird that never comes down again rises forever.
+    if rules.gravity >= 0.0 {
+        return f32::INFINITY;
+    }
+    if rules.flap_speed <= 0.0 {
+        return 0.0;
+    }
+    // Tick k moves it by flap_speed + k * gravity while that is still
+    // upwards, which sums to the ticks it rises times their average speed.
+    let (speed, loss) = (rules.flap_speed as f64, -rules.gravity as f64);
+    let ticks = (speed / loss).ceil() - 1.0;
+    ((ticks * speed - loss * ticks * (ticks + 1.0) / 2.0) * TICK_SECS as f64) as f32
+}
+
+/// Lowest the bird may let itself drop before flapping. It keeps to the
+/// middle of the opening, where a moving opening is least likely to catch
+/// it, and never lets a flap carry it into the top.
+fn lowest_flap(now: Observation, before: Option<Observation>, rules: Rules) -> f32 {
+    // How fast the opening moves, from two looks at the same pipe, and where
+    // it is headed. Moving pipes turn around, so only look a little ahead.
+    let drift = match before {
+        Some(before) if now.gap_distance < before.gap_distance => {
+            (now.gap_top + now.gap_bottom - before.gap_top - before.gap_bottom) / 2.0
+        }
+        _ => 0.0,
+    };
+    let through = (now.gap_distance + (WIDTH_PIPE + BIRD_SIZE.x) / 2.0).max(0.0);
+    let ticks = (through / (SCROLL_SPEED * TICK_SECS)).min(LOOK_AHEAD);
+    let shift = drift * ticks;
+    let rise = flap_rise(rules);
+    let middle = (now.gap_top + now.gap_bottom) / 2.0 + shift - rise / 2.0;
+    let bottom = now.gap_bottom + shift.max(0.0) + BIRD_SIZE.y / 2.0 + MARGIN;
+    let top = now.gap_top + shift.min(0.0) - BIRD_SIZE.y / 2.0 - MARGIN;
+    middle.max(bottom).min(top - rise)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The rise counted out a tick at a time.
+    fn stepped_rise(rules: Rules) -> f32 {
+        let (mut rise, mut speed) = (0.0, rules.flap_speed);
+        while speed > 0.0 {
+            speed += rules.gravity;
+            rise += speed.max(0.0) * TICK_SECS;
+        }
+        rise
+    }
+
+    #[test]
+    fn a_flap_rises_as_far_as_flying_it_out_does() {
+        for (gravity, flap_speed) in [(-5.0, 200.0), (-7.0, 150.0), (-3.0, 90.0)] {
+            let rules = Rules {
+                gravity,
+                flap_speed,
+                ..Rules::default()
+            };
+            let rise = flap_rise(rules);
+            assert!(
+                (rise - stepped_rise(rules)).abs() < 1e-3,
+                "{gravity} {flap_speed}"
+            );
+        }
+    }
+
+    #[test]
+    fn a_faint_gravity_gives_a_long_rise_at_once() {
+        let rules = Rules {
+            gravity: -1e-30,
+            ..Rules::default()
+        };
+        assert!(flap_rise(rules) > 1e6);
+        let weightless = Rules {
+            gravity: 0.0,
+            ..Rules::default()
+        };
+        assert_eq!(flap_rise(weightless), f32::INFINITY);
+    }
+}
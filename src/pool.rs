@@ -0,0 +1,302 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+/// Something made of one or more entities that scroll together, e.g. a pipe pair.
+pub trait Poolable: Send + Sync + 'static {
+    fn entities(&self) -> Vec<Entity>;
+}
+
+pub struct Slot<T> {
+    pub x: f32,
+    pub item: T,
+}
+
+/// Owns every live item of one kind of scrolling object, ordered left to right,
+/// plus the hidden ones waiting to be reused. Items leave at `exit_x` and new
+/// ones are placed `spacing` apart until `enter_x` is reached, as long as the
+/// total number of spawned items stays within `budget`.
+#[derive(Resource)]
+pub struct Pool<T: Poolable> {
+    live: VecDeque<Slot<T>>,
+    free: Vec<T>,
+    pub budget: usize,
+    pub spacing: f32,
+    pub speed: f32,
+    pub exit_x: f32,
+    pub enter_x: f32,
+    /// Where the first item goes after creation or `clear`.
+    pub start_x: f32,
+    tail_x: Option<f32>,
+}
+
+impl<T: Poolable> Pool<T> {
+    pub fn new(budget: usize, spacing: f32, start_x: f32) -> Self {
+        Pool {
+            live: VecDeque::new(),
+            free: Vec::new(),
+            budget,
+            spacing,
+            speed: 150.0,
+            exit_x: f32::NEG_INFINITY,
+            enter_x: start_x,
+            start_x,
+            tail_x: None,
+        }
+    }
+
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub fn live(&self) -> impl Iterator<Item = &Slot<T>> {
+        self.live.iter()
+    }
+
+    pub fn live_mut(&mut self) -> impl Iterator<Item = &mut Slot<T>> {
+        self.live.iter_mut()
+    }
+
+    /// Position of the next item to enter, if one is due and the budget allows it.
+    pub fn next_entry(&self) -> Option<f32> {
+        let x = self.tail_x.map_or(self.start_x, |tail| tail + self.spacing);
+        (x <= self.enter_x && self.live.len() < self.budget).then_some(x)
+    }
+
+    /// Like `next_entry`, for a pool whose items are placed relative to another
+    /// pool's: the first of `xs` past the last item, if the budget allows it.
+    pub fn next_after(&self, mut xs: impl Iterator<Item = f32>) -> Option<f32> {
+        if self.live.len() >= self.budget {
+            return None;
+        }
+        match self.tail_x {
+            Some(tail) => xs.find(|x| *x > tail + 1.0),
+            None => xs.next(),
+        }
+    }
+
+    /// Hands back a previously released item, if there is one to recycle.
+    pub fn reuse(&mut self) -> Option<T> {
+        self.free.pop()
+    }
+
+    pub fn push(&mut self, x: f32, item: T) {
+        self.tail_x = Some(x);
+        self.live.push_back(Slot { x, item });
+    }
+
+    /// Despawns every item, live or free, and starts placing again from the
+    /// start position.
+    pub fn clear(&mut self, commands: &mut Commands) {
+        let live = self.live.drain(..).map(|slot| slot.item);
+        for item in live.chain(self.free.drain(..)) {
+            for entity in item.entities() {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        self.tail_x = None;
+    }
+
+    fn scroll(&mut self, dx: f32) {
+        if let Some(tail) = &mut self.tail_x {
+            *tail -= dx;
+        }
+        for slot in self.live.iter_mut() {
+            slot.x -= dx;
+        }
+    }
+
+    fn pop_exited(&mut self) -> Option<T> {
+        if self.live.front()?.x < self.exit_x {
+            self.live.pop_front().map(|slot| slot.item)
+        } else {
+            None
+        }
+    }
+}
+
+pub fn scroll<T: Poolable>(
+    fixed_time: Res<FixedTime>,
+    mut pool: ResMut<Pool<T>>,
+    mut transforms: Query<&mut Transform>,
+) {
+    let dx = pool.speed * fixed_time.period.as_secs_f32();
+    pool.scroll(dx);
+    for slot in pool.live() {
+        for entity in slot.item.entities() {
+            if let Ok(mut transform) = transforms.get_mut(entity) {
+                transform.translation.x = slot.x;
+            }
+        }
+    }
+}
+
+/// Hides items that left the screen so they can be reused, or despawns them
+/// when the pool already holds more than its budget.
+pub fn recycle<T: Poolable>(
+    mut commands: Commands,
+    mut pool: ResMut<Pool<T>>,
+    mut visibilities: Query<&mut Visibility>,
+) {
+    while let Some(item) = pool.pop_exited() {
+        if pool.live.len() + pool.free.len() >= pool.budget {
+            for entity in item.entities() {
+                commands.entity(entity).despawn();
+            }
+            continue;
+        }
+        for entity in item.entities() {
+            if let Ok(mut visibility) = visibilities.get_mut(entity) {
+                *visibility = Visibility::Hidden;
+            }
+        }
+        pool.free.push(item);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::CommandQueue;
+
+    use super::*;
+
+    struct Item(Entity);
+
+    impl Poolable for Item {
+        fn entities(&self) -> Vec<Entity> {
+            vec![self.0]
+        }
+    }
+
+    fn spawn(app: &mut App) -> Item {
+        Item(app.world.spawn(Visibility::Inherited).id())
+    }
+
+    /// Fills `pool` the way `spawn_pipes` does and returns the positions used.
+    fn fill(app: &mut App, pool: &mut Pool<Item>) -> Vec<f32> {
+        let mut xs = Vec::new();
+        while let Some(x) = pool.next_entry() {
+            let item = spawn(app);
+            pool.push(x, item);
+            xs.push(x);
+        }
+        xs
+    }
+
+    #[test]
+    fn places_items_spacing_apart_until_enter_x() {
+        let mut app = App::new();
+        let mut pool = Pool::new(10, 100.0, 50.0);
+        pool.enter_x = 260.0;
+        assert_eq!(fill(&mut app, &mut pool), [50.0, 150.0, 250.0]);
+    }
+
+    #[test]
+    fn stops_at_the_budget() {
+        let mut app = App::new();
+        let mut pool = Pool::new(2, 100.0, 50.0);
+        pool.enter_x = 1000.0;
+        assert_eq!(fill(&mut app, &mut pool), [50.0, 150.0]);
+    }
+
+    #[test]
+    fn scrolling_makes_room_one_spacing_after_the_tail() {
+        let mut app = App::new();
+        let mut pool = Pool::new(10, 100.0, 50.0);
+        pool.enter_x = 160.0;
+        assert_eq!(fill(&mut app, &mut pool), [50.0, 150.0]);
+        assert_eq!(pool.next_entry(), None);
+        pool.scroll(100.0);
+        let xs: Vec<f32> = pool.live().map(|slot| slot.x).collect();
+        assert_eq!(xs, [-50.0, 50.0]);
+        assert_eq!(pool.next_entry(), Some(150.0));
+    }
+
+    #[test]
+    fn next_after_takes_the_first_position_past_the_tail() {
+        let mut app = App::new();
+        let mut pool = Pool::new(2, 100.0, 0.0);
+        let xs = [100.0, 200.0, 300.0];
+        assert_eq!(pool.next_after(xs.into_iter()), Some(100.0));
+        let item = spawn(&mut app);
+        pool.push(200.0, item);
+        assert_eq!(pool.next_after(xs.into_iter()), Some(300.0));
+        let item = spawn(&mut app);
+        pool.push(300.0, item);
+        assert_eq!(pool.next_after(xs.into_iter()), None);
+    }
+
+    #[test]
+    fn only_items_past_exit_x_leave() {
+        let mut app = App::new();
+        let mut pool = Pool::new(10, 100.0, 50.0);
+        pool.enter_x = 260.0;
+        pool.exit_x = 0.0;
+        fill(&mut app, &mut pool);
+        assert!(pool.pop_exited().is_none());
+        pool.scroll(60.0);
+        assert!(pool.pop_exited().is_some());
+        assert!(pool.pop_exited().is_none());
+        assert_eq!(pool.live().count(), 2);
+    }
+
+    fn recycle_all(budget: usize, items: usize) -> (App, Vec<Entity>) {
+        let mut app = App::new();
+        let mut pool = Pool::new(items, 100.0, 50.0);
+        pool.enter_x = f32::INFINITY;
+        fill(&mut app, &mut pool);
+        let entities = pool.live().map(|slot| slot.item.0).collect();
+        pool.budget = budget;
+        pool.exit_x = 1000.0;
+        app.insert_resource(pool).add_system(recycle::<Item>);
+        app.update();
+        (app, entities)
+    }
+
+    #[test]
+    fn recycled_items_are_hidden_and_reused() {
+        let (mut app, entities) = recycle_all(3, 3);
+        for entity in &entities {
+            assert_eq!(
+                app.world.get::<Visibility>(*entity),
+                Some(&Visibility::Hidden)
+            );
+        }
+        let mut pool = app.world.resource_mut::<Pool<Item>>();
+        assert_eq!(pool.live().count(), 0);
+        let reused = pool.reuse().map(|item| item.0);
+        assert!(reused.is_some_and(|entity| entities.contains(&entity)));
+    }
+
+    #[test]
+    fn items_beyond_a_shrunken_budget_are_despawned() {
+        let (mut app, entities) = recycle_all(1, 3);
+        let alive: Vec<Entity> = entities
+            .iter()
+            .copied()
+            .filter(|entity| app.world.get_entity(*entity).is_some())
+            .collect();
+        assert_eq!(alive, [entities[2]]);
+        let mut pool = app.world.resource_mut::<Pool<Item>>();
+        assert!(pool.reuse().is_some());
+        assert!(pool.reuse().is_none());
+    }
+
+    #[test]
+    fn clear_despawns_everything_and_starts_over() {
+        let mut app = App::new();
+        let mut pool = Pool::new(10, 100.0, 50.0);
+        pool.enter_x = 260.0;
+        fill(&mut app, &mut pool);
+        let entities: Vec<Entity> = pool.live().map(|slot| slot.item.0).collect();
+        let mut queue = CommandQueue::default();
+        pool.clear(&mut Commands::new(&mut queue, &app.world));
+        queue.apply(&mut app.world);
+        assert!(entities
+            .iter()
+            .all(|entity| app.world.get_entity(*entity).is_none()));
+        assert_eq!(pool.live().count(), 0);
+        assert_eq!(pool.next_entry(), Some(50.0));
+    }
+}
@@ -0,0 +1,485 @@
+use bevy::{prelude::*, sprite::collide_aabb::collide, utils::HashMap};
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+
+use crate::atlas::{Art, ArtLoader};
+use crate::coins::Coin;
+use crate::ghost::Ghost;
+use crate::layout::ViewBounds;
+use crate::obstacles::PipeGenerator;
+use crate::pool::{self, Pool, Poolable};
+use crate::skins::Hitbox;
+use crate::{
+    bird_movement, check_for_collisions, fit_to_view, flap_birds, is_editing, is_playing,
+    spawn_pipes, Bird, Pipe, DISTANCE_X_BETWEEN_PIPE, SCROLL_SPEED,
+};
+use my_bevy_game::sim::TICK_SECS;
+
+const PICKUP_SIZE: f32 = 30.0;
+/// How long the bird can fly through pipes after the shield took a hit.
+const SHIELD_GRACE_SECS: f32 = 1.0;
+
+/// What happens when a power-up is picked up while it is still active.
+pub enum Stacking {
+    /// Start the timer again from the full duration.
+    Refresh,
+    /// Add the duration on top of what is left, up to `max` seconds.
+    Extend { max: f32 },
+    /// Add a charge, up to `max`, and refresh the timer.
+    Charges { max: u32 },
+}
+
+/// Data describing one kind of power-up. The effect itself is a system that
+/// checks [`ActiveEffects::is_active`] for `name`.
+pub struct PowerUp {
+    pub name: &'static str,
+    pub label: &'static str,
+    pub color: Color,
+    /// Colour used instead with the colour-blind palette.
+    pub safe_color: Color,
+    pub duration: f32,
+    pub stacking: Stacking,
+    /// Relative chance of this power-up when one is spawned.
+    pub weight: u32,
+}
+
+#[derive(Resource)]
+pub struct PowerUps {
+    pub list: Vec<PowerUp>,
+    /// Chance that a spot between two pipes holds a power-up.
+    pub chance: f64,
+    pub slow_motion: f32,
+    pub magnet_radius: f32,
+    pub magnet_speed: f32,
+    /// Shows the power-ups in colours that stay apart for colour-blind
+    /// players.
+    pub colour_blind: bool,
+}
+
+impl Default for PowerUps {
+    fn default() -> Self {
+        PowerUps {
+            list: vec![
+                PowerUp {
+                    name: "shield",
+                    label: "Shield",
+                    color: Color::rgb(0.4, 0.7, 1.0),
+                    safe_color: Color::rgb(0.34, 0.71, 0.91),
+                    duration: 10.0,
+                    stacking: Stacking::Charges { max: 1 },
+                    weight: 2,
+                },
+                PowerUp {
+                    name: "slow_motion",
+                    label: "Slow-mo",
+                    color: Color::rgb(0.75, 0.5, 1.0),
+                    safe_color: Color::rgb(0.94, 0.89, 0.26),
+                    duration: 4.0,
+                    stacking: Stacking::Extend { max: 8.0 },
+                    weight: 1,
+                },
+                PowerUp {
+                    name: "magnet",
+                    label: "Magnet",
+                    color: Color::rgb(1.0, 0.45, 0.4),
+                    safe_color: Color::rgb(0.84, 0.37, 0.0),
+                    duration: 6.0,
+                    stacking: Stacking::Refresh,
+                    weight: 2,
+                },
+            ],
+            chance: 0.15,
+            slow_motion: 0.5,
+            magnet_radius: 160.0,
+            magnet_speed: 400.0,
+            colour_blind: false,
+        }
+    }
+}
+
+impl PowerUps {
+    fn get(&self, name: &str) -> Option<&PowerUp> {
+        self.list.iter().find(|power_up| power_up.name == name)
+    }
+
+    fn color(&self, power_up: &PowerUp) -> Color {
+        if self.colour_blind {
+            power_up.safe_color
+        } else {
+            power_up.color
+        }
+    }
+}
+
+pub struct Effect {
+    pub remaining: f32,
+    pub charges: u32,
+}
+
+/// Timed effects currently running, keyed by power-up name.
+#[derive(Resource, Default)]
+pub struct ActiveEffects {
+    effects: HashMap<&'static str, Effect>,
+}
+
+impl ActiveEffects {
+    pub fn is_active(&self, name: &str) -> bool {
+        self.effects.contains_key(name)
+    }
+
+    pub fn grant(&mut self, power_up: &PowerUp) {
+        let effect = self.effects.entry(power_up.name).or_insert(Effect {
+            remaining: 0.0,
+            charges: 0,
+        });
+        match power_up.stacking {
+            Stacking::Refresh => effect.remaining = power_up.duration,
+            Stacking::Extend { max } => {
+                effect.remaining = (effect.remaining + power_up.duration).min(max)
+            }
+            Stacking::Charges { max } => {
+                effect.charges = (effect.charges + 1).min(max);
+                effect.remaining = power_up.duration;
+            }
+        }
+    }
+
+    /// Uses up one charge of `name`, ending the effect when none are left.
+    pub fn consume(&mut self, name: &str) -> bool {
+        let Some(effect) = self.effects.get_mut(name) else {
+            return false;
+        };
+        effect.charges = effect.charges.saturating_sub(1);
+        if effect.charges == 0 {
+            self.effects.remove(name);
+        }
+        true
+    }
+
+    fn tick(&mut self, seconds: f32) {
+        self.effects.retain(|_, effect| {
+            effect.remaining -= seconds;
+            effect.remaining > 0.0
+        });
+    }
+}
+
+pub struct PowerUpCollected {
+    pub name: &'static str,
+}
+
+#[derive(Component)]
+pub struct Pickup {
+    name: Option<&'static str>,
+}
+
+/// One spot between two pipes that may or may not hold a power-up this time round.
+pub struct PickupSlot {
+    entity: Entity,
+}
+
+impl Poolable for PickupSlot {
+    fn entities(&self) -> Vec<Entity> {
+        vec![self.entity]
+    }
+}
+
+#[derive(Component)]
+struct EffectsText;
+
+pub struct PowerUpsPlugin;
+
+impl Plugin for PowerUpsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PowerUps>()
+            .init_resource::<ActiveEffects>()
+            .add_event::<PowerUpCollected>()
+            .insert_resource(
+                Pool::<PickupSlot>::new(6, DISTANCE_X_BETWEEN_PIPE, DISTANCE_X_BETWEEN_PIPE * 1.5)
+                    .with_speed(SCROLL_SPEED),
+            )
+            .add_startup_system(spawn_hud)
+            .add_system(pool::recycle::<PickupSlot>.run_if(is_playing))
+            .add_system(
+                fit_pickups_to_view
+                    .run_if(resource_changed::<ViewBounds>())
+                    .after(fit_to_view),
+            )
+            .add_system(spawn_pickups.after(spawn_pipes).run_if(not(is_editing)))
+            // Pickups are part of the run, so they move, get picked up and
+            // wear off a tick at a time like the birds.
+            .add_systems(
+                (pool::scroll::<PickupSlot>, tick_effects)
+                    .distributive_run_if(is_playing)
+                    .after(flap_birds)
+                    .before(bird_movement)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_systems(
+                (collect_pickups.run_if(is_playing), grant_effects)
+                    .chain()
+                    .after(bird_movement)
+                    .before(check_for_collisions)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(slow_motion)
+            .add_system(magnet.run_if(is_playing))
+            .add_system(update_hud);
+    }
+}
+
+fn fit_pickups_to_view(mut pickups: ResMut<Pool<PickupSlot>>, pipes: Res<Pool<Pipe>>) {
+    pickups.exit_x = pipes.exit_x;
+    pickups.budget = pipes.budget;
+}
+
+/// Pickups sit halfway between a pipe and the next one, at the height of the
+/// first pipe's opening. Which power-up, if any, follows from the course's
+/// seed and that pipe.
+fn spawn_pickups(
+    mut commands: Commands,
+    art: ArtLoader,
+    power_ups: Res<PowerUps>,
+    generator: Res<PipeGenerator>,
+    pipes: Res<Pool<Pipe>>,
+    mut pickups: ResMut<Pool<PickupSlot>>,
+    mut slots: Query<(&mut Pickup, &mut Transform, &mut Visibility, &mut Sprite)>,
+) {
+    let Some(icon) = art.resolve(Art::Region("coin_white_light")) else {
+        return;
+    };
+    loop {
+        let live: Vec<(f32, f32, i32)> = pipes
+            .live()
+            .map(|slot| (slot.x, slot.item.sim.gap, slot.item.sim.idx))
+            .collect();
+        let halfway = live.windows(2).map(|pair| (pair[0].0 + pair[1].0) / 2.0);
+        let Some(x) = pickups.next_after(halfway) else {
+            break;
+        };
+        let Some((gap, idx)) = live
+            .windows(2)
+            .find(|pair| ((pair[0].0 + pair[1].0) / 2.0 - x).abs() < 1.0)
+            .map(|pair| (pair[0].1, pair[0].2))
+        else {
+            break;
+        };
+
+        let mut rng = generator.extras(idx);
+        let weights = power_ups.list.iter().map(|power_up| power_up.weight);
+        let power_up = match WeightedIndex::new(weights) {
+            Ok(index) if rng.gen_bool(power_ups.chance) => {
+                Some(&power_ups.list[index.sample(&mut rng)])
+            }
+            _ => None,
+        };
+        let visibility = if power_up.is_some() {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        let color = power_up.map_or(Color::WHITE, |power_up| power_ups.color(power_up));
+        let name = power_up.map(|power_up| power_up.name);
+
+        if let Some(slot) = pickups.reuse() {
+            if let Ok((mut pickup, mut transform, mut slot_visibility, mut sprite)) =
+                slots.get_mut(slot.entity)
+            {
+                pickup.name = name;
+                transform.translation.y = gap;
+                *slot_visibility = visibility;
+                sprite.color = color;
+            }
+            pickups.push(x, slot);
+            continue;
+        }
+
+        let entity = commands
+            .spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color,
+                        rect: icon.rect,
+                        custom_size: Some(Vec2::splat(PICKUP_SIZE)),
+                        ..default()
+                    },
+                    texture: icon.texture.clone(),
+                    transform: Transform::from_xyz(x, gap, 0.6),
+                    visibility,
+                    ..default()
+                },
+                Pickup { name },
+            ))
+            .id();
+        pickups.push(x, PickupSlot { entity });
+    }
+}
+
+fn collect_pickups(
+    birds: Query<(&Bird, &Transform, &Hitbox), Without<Ghost>>,
+    mut pickups: Query<(&mut Pickup, &Transform, &mut Visibility), Without<Bird>>,
+    mut collected: EventWriter<PowerUpCollected>,
+) {
+    for (bird, transform, hitbox) in &birds {
+        if !bird.alive() {
+            continue;
+        }
+        for (mut pickup, pickup_transform, mut visibility) in &mut pickups {
+            let Some(name) = pickup.name else {
+                continue;
+            };
+            if collide(
+                transform.translation,
+                hitbox.0,
+                pickup_transform.translation,
+                Vec2::splat(PICKUP_SIZE),
+            )
+            .is_some()
+            {
+                pickup.name = None;
+                *visibility = Visibility::Hidden;
+                collected.send(PowerUpCollected { name });
+            }
+        }
+    }
+}
+
+fn grant_effects(
+    power_ups: Res<PowerUps>,
+    mut effects: ResMut<ActiveEffects>,
+    mut collected: EventReader<PowerUpCollected>,
+) {
+    for event in collected.iter() {
+        if let Some(power_up) = power_ups.get(event.name) {
+            effects.grant(power_up);
+        }
+    }
+}
+
+/// Effect timers count ticks, so a replay sees them run out on the same tick.
+/// A tick of slow motion takes longer, which keeps it from stretching itself.
+fn tick_effects(power_ups: Res<PowerUps>, mut effects: ResMut<ActiveEffects>) {
+    let speed = if effects.is_active("slow_motion") {
+        power_ups.slow_motion
+    } else {
+        1.0
+    };
+    effects.tick(TICK_SECS / speed);
+}
+
+/// Lets the shield take a hit in place of the bird. Returns whether it did.
+pub fn absorb_hit(effects: &mut ActiveEffects) -> bool {
+    if effects.is_active("shield_grace") {
+        return true;
+    }
+    if !effects.consume("shield") {
+        return false;
+    }
+    effects.effects.insert(
+        "shield_grace",
+        Effect {
+            remaining: SHIELD_GRACE_SECS,
+            charges: 0,
+        },
+    );
+    true
+}
+
+fn slow_motion(power_ups: Res<PowerUps>, effects: Res<ActiveEffects>, mut time: ResMut<Time>) {
+    let speed = if effects.is_active("slow_motion") {
+        power_ups.slow_motion
+    } else {
+        1.0
+    };
+    if time.relative_speed() != speed {
+        time.set_relative_speed(speed);
+    }
+}
+
+fn magnet(
+    time: Res<Time>,
+    power_ups: Res<PowerUps>,
+    effects: Res<ActiveEffects>,
+    birds: Query<(&Bird, &Transform), (Without<Ghost>, Without<Coin>)>,
+    mut coins: Query<(&mut Transform, &GlobalTransform, &Visibility), With<Coin>>,
+) {
+    if !effects.is_active("magnet") {
+        return;
+    }
+    for (bird, bird_transform) in &birds {
+        if !bird.alive() {
+            continue;
+        }
+        for (mut transform, global, visibility) in &mut coins {
+            if *visibility == Visibility::Hidden {
+                continue;
+            }
+            let to_bird = bird_transform.translation.truncate() - global.translation().truncate();
+            let distance = to_bird.length();
+            if distance > power_ups.magnet_radius || distance < f32::EPSILON {
+                continue;
+            }
+            let step = (power_ups.magnet_speed * time.delta_seconds()).min(distance);
+            // Coin groups are never rotated or scaled, so world and local offsets match.
+            transform.translation += (to_bird / distance * step).extend(0.0);
+        }
+    }
+}
+
+fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                font_size: 22.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_text_alignment(TextAlignment::Right)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(10.0),
+                right: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+        EffectsText,
+    ));
+}
+
+fn update_hud(
+    power_ups: Res<PowerUps>,
+    effects: Res<ActiveEffects>,
+    mut texts: Query<&mut Text, With<EffectsText>>,
+) {
+    if !effects.is_changed() {
+        return;
+    }
+    for mut text in &mut texts {
+        let style = text.sections[0].style.clone();
+        let mut sections = Vec::new();
+        for power_up in &power_ups.list {
+            let Some(effect) = effects.effects.get(power_up.name) else {
+                continue;
+            };
+            let charges = if effect.charges > 1 {
+                format!(" x{}", effect.charges)
+            } else {
+                String::new()
+            };
+            sections.push(TextSection::new(
+                format!("{}{} {:.1}s\n", power_up.label, charges, effect.remaining),
+                TextStyle {
+                    color: power_ups.color(power_up),
+                    ..style.clone()
+                },
+            ));
+        }
+        if sections.is_empty() {
+            sections.push(TextSection::new("", style));
+        }
+        text.sections = sections;
+    }
+}
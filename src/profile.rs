@@ -0,0 +1,93 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use my_bevy_game::save::{self, Folder, Versioned};
+use serde::{Deserialize, Serialize};
+
+const FILE: &str = "profile.ron";
+
+/// Per-player progress that survives restarts.
+#[derive(Resource, Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Profile {
+    pub skin: String,
+    pub unlocked_skins: Vec<String>,
+    pub coins: u32,
+    /// Best star rating per level name.
+    pub level_stars: BTreeMap<String, u8>,
+    /// Best endless score this player flew themselves.
+    pub high_score: i32,
+}
+
+impl Versioned for Profile {
+    const VERSION: u32 = 1;
+
+    /// Before version 1 the profile was plain RON without the high score,
+    /// and before that there was only a high score, a bare number in
+    /// `assets/data/score`.
+    fn migrate(version: u32, text: &str) -> Result<Self, String> {
+        match version {
+            0 => match text.trim().parse() {
+                Ok(high_score) => Ok(Profile {
+                    high_score,
+                    ..default()
+                }),
+                Err(_) => ron::from_str(text).map_err(|err| err.to_string()),
+            },
+            _ => Err(format!("no profile schema {version}")),
+        }
+    }
+}
+
+impl Profile {
+    /// The saved profile, or a new one that keeps the high score from the
+    /// `data/score` file in `assets` of old versions.
+    pub fn load(assets: &str) -> Self {
+        let Some(storage) = save::platform_storage(Folder::Data) else {
+            return Profile::default();
+        };
+        let loaded = save::load::<Profile>(storage.as_ref(), FILE);
+        for problem in &loaded.problems {
+            warn!("skipping unreadable profile: {problem}");
+        }
+        if let Some(profile) = loaded.data {
+            return profile;
+        }
+        let legacy = std::path::Path::new(assets).join("data").join("score");
+        match std::fs::read_to_string(&legacy).map(|text| Profile::migrate(0, &text)) {
+            Ok(Ok(profile)) => {
+                info!(
+                    "carrying over high score {} from {}",
+                    profile.high_score,
+                    legacy.display()
+                );
+                profile.save();
+                profile
+            }
+            _ => Profile::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        let Some(mut storage) = save::platform_storage(Folder::Data) else {
+            return;
+        };
+        if let Err(err) = save::save(storage.as_mut(), FILE, self) {
+            warn!("could not save profile: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_profiles_are_brought_up_to_date() {
+        let profile: Profile = save::decode("12\n").unwrap();
+        assert_eq!(profile.high_score, 12);
+        let profile: Profile = save::decode("(skin: \"robin\", coins: 3)").unwrap();
+        assert_eq!((profile.skin.as_str(), profile.coins), ("robin", 3));
+        assert_eq!(profile.high_score, 0);
+    }
+}
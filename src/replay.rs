@@ -0,0 +1,321 @@
+use std::{fs, path::Path, path::PathBuf};
+
+use bevy::prelude::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::course::Difficulty;
+use crate::sim::{Race, BIRD_SIZE, SKIN_HITBOXES};
+
+/// Everything needed to fly an endless run again: the course comes from the
+/// seed and difficulty, the bird from the ticks it flapped on.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Replay {
+    pub seed: u64,
+    pub difficulty: Difficulty,
+    /// Ticks since the first flap on which the bird flapped.
+    pub flaps: Vec<u32>,
+    /// Tick on which the bird crashed.
+    pub end: u32,
+    pub score: i32,
+    /// Size of the bird's hitbox, which comes with its skin.
+    #[serde(default = "default_hitbox")]
+    pub hitbox: (f32, f32),
+    /// Tick on which a shield first took a hit in place of the bird. The
+    /// simulation has no power-ups, so such a run cannot be verified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shielded: Option<u32>,
+}
+
+/// Longest run a replay may claim, an hour of ticks at 60 a second.
+const MAX_TICKS: u32 = 60 * 60 * 60;
+
+fn default_hitbox() -> (f32, f32) {
+    (BIRD_SIZE.x, BIRD_SIZE.y)
+}
+
+/// Where flying a replay again first disagrees with the replay.
+#[derive(Debug)]
+pub struct Divergence {
+    pub tick: u32,
+    pub reason: String,
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "tick {}: {}", self.tick, self.reason)
+    }
+}
+
+impl Replay {
+    /// Where the personal best is kept.
+    pub fn best_path() -> Option<PathBuf> {
+        Some(
+            dirs::data_dir()?
+                .join("flappy-bevy")
+                .join("best.replay.ron"),
+        )
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        ron::from_str(&text).map_err(|err| err.to_string())
+    }
+
+    /// Flies the flaps on the replay's course the way the game would have,
+    /// checking the run against what the replay claims as it goes. Returns
+    /// the finished race, whose bird 0 is the replayed one, or the first tick
+    /// on which the two part ways.
+    pub fn verify(&self) -> Result<Race, Divergence> {
+        let diverge = |tick, reason| Err(Divergence { tick, reason });
+        if let Some(tick) = self.shielded {
+            return diverge(
+                tick,
+                "a shield took a hit, which the simulation cannot check".to_string(),
+            );
+        }
+        // The recording starts with the flap that starts the run.
+        if self.flaps.first() != Some(&0) {
+            return diverge(0, "the run does not start with a flap".to_string());
+        }
+        let hitbox = Vec2::from(self.hitbox);
+        if !SKIN_HITBOXES.contains(&hitbox) {
+            return diverge(
+                0,
+                format!("no skin has a {} by {} hitbox", hitbox.x, hitbox.y),
+            );
+        }
+        if self.end > MAX_TICKS {
+            return diverge(MAX_TICKS, "the run is longer than an hour".to_string());
+        }
+        if let Some(pair) = self.flaps.windows(2).find(|pair| pair[1] < pair[0]) {
+            return diverge(pair[1], format!("flap {} comes after {}", pair[1], pair[0]));
+        }
+        let mut race = Race::new(self.seed, self.difficulty);
+        race.add_sized_bird(0.0, hitbox);
+        let mut flaps = self.flaps.iter().peekable();
+        while race.birds[0].alive() {
+            // Flying on past the claimed end cannot make the claim true.
+            if race.tick > self.end {
+                return diverge(self.end, format!("still flying after tick {}", self.end));
+            }
+            while flaps.next_if(|&&tick| tick <= race.tick).is_some() {
+                race.flap(0);
+            }
+            let tick = race.tick;
+            race.step();
+            let flown = race.birds[0].score;
+            if flown > self.score {
+                return diverge(
+                    tick,
+                    format!("scores {flown}, more than the {} claimed", self.score),
+                );
+            }
+        }
+        let bird = &race.birds[0];
+        let down = bird.down_at.expect("the loop ran until the bird was down");
+        if self.end != down {
+            return diverge(
+                down.min(self.end),
+                format!("crashes on tick {down}, not {}", self.end),
+            );
+        }
+        if let Some(flap) = flaps.next() {
+            return diverge(down, format!("flaps on tick {flap} after crashing"));
+        }
+        if bird.score != self.score {
+            return diverge(
+                down,
+                format!("scores {}, not the {} claimed", bird.score, self.score),
+            );
+        }
+        Ok(race)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = ron::ser::to_string(self).map_err(|err| err.to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        fs::write(path, text).map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::{Action, Env};
+    use crate::pilot::{Pilot, Skill};
+    use crate::sim::ATLAS_BIRD_SIZE;
+
+    /// A run the autopilot flies, which starts with a flap like the game's.
+    fn flown() -> Replay {
+        let (seed, difficulty) = (4, Difficulty::Normal);
+        let mut env = Env::new(difficulty);
+        let mut observation = env.reset(seed);
+        let mut pilot = Pilot::new(Skill::Average, seed);
+        let mut flaps = Vec::new();
+        while !env.done() {
+            let action = if flaps.is_empty() {
+                Action::Flap
+            } else {
+                pilot.act(observation)
+            };
+            if action == Action::Flap {
+                flaps.push(env.tick());
+            }
+            observation = env.step(action).0;
+        }
+        let replay = Replay {
+            seed,
+            difficulty,
+            flaps,
+            end: env.race().birds[0].down_at.unwrap(),
+            score: env.score(),
+            hitbox: default_hitbox(),
+            shielded: None,
+        };
+        assert!(replay.score >= 2, "the pilot should pass a few pipes");
+        replay
+    }
+
+    fn rejected(replay: &Replay) -> Divergence {
+        match replay.verify() {
+            Ok(_) => panic!("the replay should be rejected"),
+            Err(divergence) => divergence,
+        }
+    }
+
+    #[test]
+    fn a_genuine_run_is_accepted() {
+        let replay = flown();
+        let race = replay.verify().unwrap();
+        assert_eq!(race.birds[0].score, replay.score);
+        assert_eq!(race.birds[0].down_at, Some(replay.end));
+    }
+
+    #[test]
+    fn a_lower_claim_is_rejected_when_the_run_passes_it() {
+        let mut replay = flown();
+        replay.score -= 1;
+        // The tick on which the bird passes the pipe it did not claim.
+        let mut race = Race::new(replay.seed, replay.difficulty);
+        race.add_bird(0.0);
+        let mut flaps = replay.flaps.iter().peekable();
+        while race.birds[0].score <= replay.score {
+            while flaps.next_if(|&&tick| tick <= race.tick).is_some() {
+                race.flap(0);
+            }
+            race.step();
+        }
+        let divergence = rejected(&replay);
+        assert_eq!(divergence.tick, race.tick - 1);
+        assert!(divergence.reason.contains("more than"), "{divergence}");
+    }
+
+    #[test]
+    fn an_inflated_score_is_rejected_where_the_bird_crashes() {
+        let mut replay = flown();
+        replay.score += 5;
+        let divergence = rejected(&replay);
+        assert_eq!(divergence.tick, replay.end);
+        assert!(divergence.reason.starts_with("scores"), "{divergence}");
+    }
+
+    #[test]
+    fn the_wrong_end_is_rejected() {
+        let mut replay = flown();
+        let end = replay.end;
+        replay.end += 30;
+        let divergence = rejected(&replay);
+        assert_eq!(divergence.tick, end);
+        assert_eq!(
+            divergence.reason,
+            format!("crashes on tick {end}, not {}", replay.end)
+        );
+    }
+
+    #[test]
+    fn flaps_out_of_order_are_rejected() {
+        let mut replay = flown();
+        replay.flaps.swap(1, 2);
+        let divergence = rejected(&replay);
+        assert_eq!(divergence.tick, replay.flaps[2]);
+        assert!(divergence.reason.contains("comes after"), "{divergence}");
+    }
+
+    #[test]
+    fn a_run_must_start_with_a_flap() {
+        let mut replay = flown();
+        replay.flaps.remove(0);
+        let divergence = rejected(&replay);
+        assert_eq!(divergence.tick, 0);
+    }
+
+    #[test]
+    fn a_shielded_run_is_rejected() {
+        let mut replay = flown();
+        replay.shielded = Some(40);
+        assert_eq!(rejected(&replay).tick, 40);
+    }
+
+    #[test]
+    fn the_recorded_hitbox_is_flown() {
+        let mut replay = flown();
+        replay.hitbox = (ATLAS_BIRD_SIZE.x, ATLAS_BIRD_SIZE.y);
+        let mut race = Race::new(replay.seed, replay.difficulty);
+        race.add_sized_bird(0.0, ATLAS_BIRD_SIZE);
+        let mut flaps = replay.flaps.iter().peekable();
+        while race.birds[0].alive() {
+            while flaps.next_if(|&&tick| tick <= race.tick).is_some() {
+                race.flap(0);
+            }
+            race.step();
+        }
+        replay
+            .flaps
+            .retain(|&tick| tick <= race.birds[0].down_at.unwrap());
+        replay.end = race.birds[0].down_at.unwrap();
+        replay.score = race.birds[0].score;
+        let verified = replay.verify().unwrap();
+        assert_eq!(verified.birds[0].size, ATLAS_BIRD_SIZE);
+
+        let text = ron::to_string(&flown()).unwrap();
+        assert!(!text.contains("shielded"));
+        let old = text.replace(",hitbox:(26.0,26.0)", "");
+        assert_ne!(old, text);
+        let loaded: Replay = ron::from_str(&old).unwrap();
+        assert_eq!(loaded.hitbox, (26.0, 26.0));
+    }
+
+    #[test]
+    fn only_the_hitboxes_of_skins_are_flown() {
+        for hitbox in [
+            (1.0, 1.0),
+            (-26.0, 26.0),
+            (f32::NEG_INFINITY, 26.0),
+            (26.0, f32::NAN),
+        ] {
+            let mut replay = flown();
+            replay.hitbox = hitbox;
+            let divergence = rejected(&replay);
+            assert_eq!(divergence.tick, 0);
+            assert!(divergence.reason.starts_with("no skin"), "{divergence}");
+        }
+    }
+
+    #[test]
+    fn flying_stops_at_the_claimed_end() {
+        let mut replay = flown();
+        replay.end -= 10;
+        replay.flaps.retain(|&tick| tick <= replay.end);
+        let divergence = rejected(&replay);
+        assert_eq!(divergence.tick, replay.end);
+        assert!(
+            divergence.reason.starts_with("still flying"),
+            "{divergence}"
+        );
+
+        replay.end = u32::MAX;
+        assert_eq!(rejected(&replay).tick, MAX_TICKS);
+    }
+}
@@ -0,0 +1,368 @@
+//! Save files that outlive the game's own changes. Each file is wrapped in an
+//! envelope with the schema version and a checksum of the data, so older
+//! saves can be brought up to date and damaged ones are noticed. Every save
+//! keeps the previous copies as backups, and loading falls back to them when
+//! the newest copy is damaged.
+//!
+//! Where the files go is up to a [`Storage`]: a directory on desktop, local
+//! storage in the browser, or memory when nothing should touch the disk.
+
+use std::collections::BTreeMap;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Older copies kept next to each save, `name.1` being the newest.
+pub const BACKUPS: usize = 2;
+
+/// Somewhere to keep named text files.
+pub trait Storage {
+    /// The file's text, or `None` if there is no such file.
+    fn read(&self, name: &str) -> Result<Option<String>, String>;
+    fn write(&mut self, name: &str, text: &str) -> Result<(), String>;
+    /// Removes the file; one that is not there is already gone.
+    fn remove(&mut self, name: &str) -> Result<(), String>;
+}
+
+/// Files in one directory, created on the first write.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileStorage {
+    dir: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileStorage {
+    pub fn new(dir: impl Into<std::path::PathBuf>) -> Self {
+        FileStorage { dir: dir.into() }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Storage for FileStorage {
+    fn read(&self, name: &str) -> Result<Option<String>, String> {
+        let path = self.dir.join(name);
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Ok(Some(text)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(format!("{}: {err}", path.display())),
+        }
+    }
+
+    /// Writes next to the file and renames it over, so a crash midway
+    /// leaves the old file whole.
+    fn write(&mut self, name: &str, text: &str) -> Result<(), String> {
+        let path = self.dir.join(name);
+        let temp = self.dir.join(format!("{name}.tmp"));
+        std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(&temp, text))
+            .and_then(|_| std::fs::rename(&temp, &path))
+            .map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    fn remove(&mut self, name: &str) -> Result<(), String> {
+        let path = self.dir.join(name);
+        match std::fs::remove_file(&path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("{}: {err}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The browser's local storage, with every name under `prefix`.
+#[cfg(target_arch = "wasm32")]
+pub struct LocalStorage {
+    storage: web_sys::Storage,
+    prefix: String,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl LocalStorage {
+    /// `None` when the browser has no local storage or it is turned off.
+    pub fn new(prefix: &str) -> Option<Self> {
+        let storage = web_sys::window()?.local_storage().ok()??;
+        Some(LocalStorage {
+            storage,
+            prefix: prefix.to_string(),
+        })
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Storage for LocalStorage {
+    fn read(&self, name: &str) -> Result<Option<String>, String> {
+        self.storage
+            .get_item(&format!("{}{name}", self.prefix))
+            .map_err(|err| format!("{err:?}"))
+    }
+
+    fn write(&mut self, name: &str, text: &str) -> Result<(), String> {
+        self.storage
+            .set_item(&format!("{}{name}", self.prefix), text)
+            .map_err(|err| format!("{err:?}"))
+    }
+
+    fn remove(&mut self, name: &str) -> Result<(), String> {
+        self.storage
+            .remove_item(&format!("{}{name}", self.prefix))
+            .map_err(|err| format!("{err:?}"))
+    }
+}
+
+/// Files kept in memory only, e.g. for tests and tools.
+#[derive(Default, Clone, Debug)]
+pub struct MemoryStorage {
+    pub files: BTreeMap<String, String>,
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self, name: &str) -> Result<Option<String>, String> {
+        Ok(self.files.get(name).cloned())
+    }
+
+    fn write(&mut self, name: &str, text: &str) -> Result<(), String> {
+        self.files.insert(name.to_string(), text.to_string());
+        Ok(())
+    }
+
+    fn remove(&mut self, name: &str) -> Result<(), String> {
+        self.files.remove(name);
+        Ok(())
+    }
+}
+
+/// Which of the user's folders a save belongs in on desktop.
+pub enum Folder {
+    /// Preferences.
+    Config,
+    /// Progress.
+    Data,
+}
+
+/// Where the game keeps its saves on this platform: its own directory in
+/// the user's `folder` on desktop, local storage in the browser. `None` if
+/// there is nowhere to keep them.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn platform_storage(folder: Folder) -> Option<Box<dyn Storage>> {
+    let dir = match folder {
+        Folder::Config => dirs::config_dir()?,
+        Folder::Data => dirs::data_dir()?,
+    };
+    Some(Box::new(FileStorage::new(dir.join("flappy-bevy"))))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn platform_storage(_folder: Folder) -> Option<Box<dyn Storage>> {
+    Some(Box::new(LocalStorage::new("flappy-bevy/")?))
+}
+
+/// Data with a schema version that knows how to read its older versions.
+pub trait Versioned: Serialize + DeserializeOwned {
+    const VERSION: u32;
+
+    /// Reads `text` written by an older `version`. Version 0 is whatever
+    /// was saved before there were envelopes.
+    fn migrate(version: u32, text: &str) -> Result<Self, String>;
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Envelope {
+    version: u32,
+    checksum: u64,
+    /// The data itself, kept as text so the checksum covers exactly what
+    /// was written.
+    data: String,
+}
+
+/// FNV-1a, enough to notice a damaged file; it does not stop anyone from
+/// editing one on purpose.
+fn checksum(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+pub fn encode<T: Versioned>(value: &T) -> Result<String, String> {
+    let data = ron::to_string(value).map_err(|err| err.to_string())?;
+    let envelope = Envelope {
+        version: T::VERSION,
+        checksum: checksum(&data),
+        data,
+    };
+    ron::ser::to_string_pretty(&envelope, Default::default()).map_err(|err| err.to_string())
+}
+
+/// Whether `text` starts the way an envelope does, so that a damaged one is
+/// reported rather than read as a save from before envelopes.
+fn looks_like_envelope(text: &str) -> bool {
+    text.trim_start()
+        .strip_prefix('(')
+        .is_some_and(|rest| rest.trim_start().starts_with("version"))
+}
+
+pub fn decode<T: Versioned>(text: &str) -> Result<T, String> {
+    if !looks_like_envelope(text) {
+        return T::migrate(0, text);
+    }
+    let envelope: Envelope = ron::from_str(text).map_err(|err| err.to_string())?;
+    if checksum(&envelope.data) != envelope.checksum {
+        return Err("checksum does not match, the file is damaged".to_string());
+    }
+    match envelope.version {
+        version if version == T::VERSION => {
+            ron::from_str(&envelope.data).map_err(|err| err.to_string())
+        }
+        version if version > T::VERSION => Err(format!(
+            "saved by a newer version of the game (schema {version}, this one reads up to {})",
+            T::VERSION
+        )),
+        version => T::migrate(version, &envelope.data),
+    }
+}
+
+fn backup_name(name: &str, index: usize) -> String {
+    format!("{name}.{index}")
+}
+
+/// What was found when loading a save.
+pub struct Loaded<T> {
+    /// The newest copy that could be read, or `None` if nothing was saved
+    /// or every copy is damaged.
+    pub data: Option<T>,
+    /// What was wrong with the copies passed over.
+    pub problems: Vec<String>,
+}
+
+/// Reads `name`, or its newest readable backup if it is damaged.
+pub fn load<T: Versioned>(storage: &dyn Storage, name: &str) -> Loaded<T> {
+    let mut problems = Vec::new();
+    let copies = std::iter::once(name.to_string())
+        .chain((1..=BACKUPS).map(|index| backup_name(name, index)));
+    for copy in copies {
+        let text = match storage.read(&copy) {
+            Ok(Some(text)) => text,
+            Ok(None) => continue,
+            Err(err) => {
+                problems.push(err);
+                continue;
+            }
+        };
+        match decode(&text) {
+            Ok(data) => {
+                return Loaded {
+                    data: Some(data),
+                    problems,
+                }
+            }
+            Err(err) => problems.push(format!("{copy}: {err}")),
+        }
+    }
+    Loaded {
+        data: None,
+        problems,
+    }
+}
+
+/// Writes `value` as `name`, moving the copies already there down the
+/// backups and dropping the oldest.
+pub fn save<T: Versioned>(storage: &mut dyn Storage, name: &str, value: &T) -> Result<(), String> {
+    let text = encode(value)?;
+    for index in (1..=BACKUPS).rev() {
+        let from = match index {
+            1 => name.to_string(),
+            _ => backup_name(name, index - 1),
+        };
+        if let Some(older) = storage.read(&from)? {
+            storage.write(&backup_name(name, index), &older)?;
+        }
+    }
+    storage.write(name, &text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A save whose first version was a bare number.
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Best {
+        score: i32,
+    }
+
+    impl Versioned for Best {
+        const VERSION: u32 = 1;
+
+        fn migrate(version: u32, text: &str) -> Result<Self, String> {
+            match version {
+                0 => text
+                    .trim()
+                    .parse()
+                    .map(|score| Best { score })
+                    .map_err(|err| format!("not a score: {err}")),
+                _ => Err(format!("no best schema {version}")),
+            }
+        }
+    }
+
+    fn saved(storage: &MemoryStorage, name: &str) -> Option<Best> {
+        decode(storage.files.get(name)?).ok()
+    }
+
+    #[test]
+    fn a_save_loads_back() {
+        let mut storage = MemoryStorage::default();
+        save(&mut storage, "best", &Best { score: 12 }).unwrap();
+        let loaded = load::<Best>(&storage, "best");
+        assert_eq!(loaded.data, Some(Best { score: 12 }));
+        assert!(loaded.problems.is_empty());
+    }
+
+    #[test]
+    fn a_damaged_save_falls_back_to_the_newest_backup() {
+        let mut storage = MemoryStorage::default();
+        save(&mut storage, "best", &Best { score: 3 }).unwrap();
+        save(&mut storage, "best", &Best { score: 4 }).unwrap();
+        let text = storage.files["best"].replace("score:4", "score:40");
+        storage.files.insert("best".to_string(), text);
+        let loaded = load::<Best>(&storage, "best");
+        assert_eq!(loaded.data, Some(Best { score: 3 }));
+        assert_eq!(loaded.problems.len(), 1);
+        assert!(
+            loaded.problems[0].contains("checksum"),
+            "{:?}",
+            loaded.problems
+        );
+    }
+
+    #[test]
+    fn saving_moves_older_copies_down_the_backups() {
+        let mut storage = MemoryStorage::default();
+        for score in 1..=4 {
+            save(&mut storage, "best", &Best { score }).unwrap();
+        }
+        assert_eq!(saved(&storage, "best"), Some(Best { score: 4 }));
+        assert_eq!(saved(&storage, "best.1"), Some(Best { score: 3 }));
+        assert_eq!(saved(&storage, "best.2"), Some(Best { score: 2 }));
+        assert_eq!(storage.files.len(), 1 + BACKUPS);
+    }
+
+    #[test]
+    fn a_save_from_a_newer_game_is_refused() {
+        let data = "(score:5)";
+        let text = format!("(version: 2, checksum: {}, data: {data:?})", checksum(data));
+        let err = decode::<Best>(&text).unwrap_err();
+        assert!(err.contains("newer version"), "{err}");
+    }
+
+    #[test]
+    fn a_save_from_before_envelopes_is_migrated() {
+        assert_eq!(decode::<Best>("12\n"), Ok(Best { score: 12 }));
+    }
+
+    #[test]
+    fn a_broken_envelope_is_not_read_as_an_old_save() {
+        let err = decode::<Best>("(version: 1, checksum: 7").unwrap_err();
+        assert!(!err.starts_with("not a score"), "{err}");
+    }
+}
@@ -0,0 +1,315 @@
+//! Scores shared on an online leaderboard. Every submission carries the
+//! replay, so the server can fly it again and turn away scores that could
+//! not have happened. Submissions that cannot be delivered wait in a
+//! [`ScoreQueue`] until the server can be reached again.
+//!
+//! The HTTP backend speaks JSON:
+//!
+//! POST /scores with a `Submission`, answered 2xx when accepted and 4xx with
+//! a reason when rejected.
+//! GET /scores?limit=N, answered with the best `ScoreEntry`s, best first.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::replay::Replay;
+use crate::save::Versioned;
+
+/// Where the development server listens, see `src/bin/scoreboard.rs`.
+pub const DEFAULT_URL: &str = "http://127.0.0.1:8080";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Submission {
+    pub name: String,
+    /// Holds the seed, difficulty and claimed score as well as the flaps.
+    pub replay: Replay,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ScoreEntry {
+    pub name: String,
+    pub score: i32,
+}
+
+#[derive(Debug)]
+pub enum SubmitError {
+    /// The server could not be reached or failed; worth trying again later.
+    Unreachable(String),
+    /// The server turned the score down; trying again will not help.
+    Rejected(String),
+}
+
+impl std::fmt::Display for SubmitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SubmitError::Unreachable(reason) => write!(f, "unreachable: {reason}"),
+            SubmitError::Rejected(reason) => write!(f, "rejected: {reason}"),
+        }
+    }
+}
+
+/// An online leaderboard.
+pub trait LeaderboardBackend {
+    fn submit(&mut self, submission: &Submission) -> Result<(), SubmitError>;
+    /// The best `limit` scores, best first.
+    fn top(&mut self, limit: usize) -> Result<Vec<ScoreEntry>, SubmitError>;
+}
+
+/// A leaderboard served over HTTP.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct HttpBackend {
+    url: String,
+    timeout: std::time::Duration,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl HttpBackend {
+    pub fn new(url: &str) -> Self {
+        HttpBackend {
+            url: url.trim_end_matches('/').to_string(),
+            timeout: std::time::Duration::from_secs(5),
+        }
+    }
+
+    fn check(response: attohttpc::Response) -> Result<attohttpc::Response, SubmitError> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().unwrap_or_default();
+        let reason = format!("{status} {}", body.trim());
+        if status.is_client_error() {
+            Err(SubmitError::Rejected(reason))
+        } else {
+            Err(SubmitError::Unreachable(reason))
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl LeaderboardBackend for HttpBackend {
+    fn submit(&mut self, submission: &Submission) -> Result<(), SubmitError> {
+        let unreachable = |err: attohttpc::Error| SubmitError::Unreachable(err.to_string());
+        let response = attohttpc::post(format!("{}/scores", self.url))
+            .timeout(self.timeout)
+            .json(submission)
+            .map_err(unreachable)?
+            .send()
+            .map_err(unreachable)?;
+        Self::check(response).map(|_| ())
+    }
+
+    fn top(&mut self, limit: usize) -> Result<Vec<ScoreEntry>, SubmitError> {
+        let unreachable = |err: attohttpc::Error| SubmitError::Unreachable(err.to_string());
+        let response = attohttpc::get(format!("{}/scores", self.url))
+            .param("limit", limit)
+            .timeout(self.timeout)
+            .send()
+            .map_err(unreachable)?;
+        Self::check(response)?.json().map_err(unreachable)
+    }
+}
+
+/// A leaderboard kept in memory, e.g. for tests.
+#[derive(Default)]
+pub struct MemoryBackend {
+    pub scores: Vec<ScoreEntry>,
+    /// Answers every call as if the server were down.
+    pub offline: bool,
+    /// Turns down replays that do not hold up, like the server does.
+    pub verify: bool,
+}
+
+impl LeaderboardBackend for MemoryBackend {
+    fn submit(&mut self, submission: &Submission) -> Result<(), SubmitError> {
+        if self.offline {
+            return Err(SubmitError::Unreachable("offline".to_string()));
+        }
+        if self.verify {
+            if let Err(divergence) = submission.replay.verify() {
+                return Err(SubmitError::Rejected(divergence.to_string()));
+            }
+        }
+        self.scores.push(ScoreEntry {
+            name: submission.name.clone(),
+            score: submission.replay.score,
+        });
+        self.scores
+            .sort_by_key(|entry| std::cmp::Reverse(entry.score));
+        Ok(())
+    }
+
+    fn top(&mut self, limit: usize) -> Result<Vec<ScoreEntry>, SubmitError> {
+        if self.offline {
+            return Err(SubmitError::Unreachable("offline".to_string()));
+        }
+        Ok(self.scores.iter().take(limit).cloned().collect())
+    }
+}
+
+/// What became of a submission when the queue was flushed.
+pub enum Delivery {
+    Accepted(Submission),
+    Rejected(Submission, String),
+    /// The rest stay queued because the server could not be reached.
+    Held(String),
+}
+
+/// Submissions waiting to be delivered, oldest first. It is saved, so
+/// scores flown offline go out the next time the game can reach the server.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ScoreQueue {
+    pub pending: VecDeque<Submission>,
+}
+
+impl Versioned for ScoreQueue {
+    const VERSION: u32 = 1;
+
+    fn migrate(version: u32, _text: &str) -> Result<Self, String> {
+        Err(format!("no score queue schema {version}"))
+    }
+}
+
+impl ScoreQueue {
+    pub fn push(&mut self, submission: Submission) {
+        self.pending.push_back(submission);
+    }
+
+    /// Sends what is waiting, in order, until the backend cannot be reached.
+    /// Rejected submissions are dropped.
+    pub fn flush(&mut self, backend: &mut dyn LeaderboardBackend) -> Vec<Delivery> {
+        let mut deliveries = Vec::new();
+        while let Some(submission) = self.pending.front() {
+            match backend.submit(submission) {
+                Ok(()) => {
+                    let submission = self.pending.pop_front().expect("checked above");
+                    deliveries.push(Delivery::Accepted(submission));
+                }
+                Err(SubmitError::Rejected(reason)) => {
+                    let submission = self.pending.pop_front().expect("checked above");
+                    deliveries.push(Delivery::Rejected(submission, reason));
+                }
+                Err(SubmitError::Unreachable(reason)) => {
+                    deliveries.push(Delivery::Held(reason));
+                    break;
+                }
+            }
+        }
+        deliveries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::course::Difficulty;
+    use crate::sim::{Race, BIRD_SIZE};
+
+    fn submission(name: &str, replay: Replay) -> Submission {
+        Submission {
+            name: name.to_string(),
+            replay,
+        }
+    }
+
+    /// A run that claims `score` without flying for it.
+    fn claim(score: i32) -> Replay {
+        Replay {
+            seed: 1,
+            difficulty: Difficulty::Normal,
+            flaps: vec![0],
+            end: 0,
+            score,
+            hitbox: (BIRD_SIZE.x, BIRD_SIZE.y),
+            shielded: None,
+        }
+    }
+
+    /// A run that flaps once and falls, which holds up.
+    fn genuine() -> Replay {
+        let mut replay = claim(0);
+        let mut race = Race::new(replay.seed, replay.difficulty);
+        race.add_bird(0.0);
+        race.flap(0);
+        while race.birds[0].alive() {
+            race.step();
+        }
+        replay.end = race.birds[0].down_at.unwrap();
+        replay
+    }
+
+    fn names(deliveries: &[Delivery]) -> Vec<String> {
+        deliveries
+            .iter()
+            .map(|delivery| match delivery {
+                Delivery::Accepted(submission) => format!("accepted {}", submission.name),
+                Delivery::Rejected(submission, _) => format!("rejected {}", submission.name),
+                Delivery::Held(_) => "held".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn accepted_scores_go_out_in_order_and_rank_best_first() {
+        let mut backend = MemoryBackend::default();
+        let mut queue = ScoreQueue::default();
+        for (name, score) in [("ann", 3), ("bob", 7), ("cat", 5)] {
+            queue.push(submission(name, claim(score)));
+        }
+        let deliveries = queue.flush(&mut backend);
+        assert_eq!(
+            names(&deliveries),
+            ["accepted ann", "accepted bob", "accepted cat"]
+        );
+        assert!(queue.pending.is_empty());
+        let top: Vec<_> = backend
+            .top(2)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.name, entry.score))
+            .collect();
+        assert_eq!(top, [("bob".to_string(), 7), ("cat".to_string(), 5)]);
+    }
+
+    #[test]
+    fn a_rejected_score_is_dropped_and_the_rest_still_go_out() {
+        let mut backend = MemoryBackend {
+            verify: true,
+            ..Default::default()
+        };
+        let mut queue = ScoreQueue::default();
+        queue.push(submission("ann", genuine()));
+        queue.push(submission("bob", claim(5)));
+        queue.push(submission("cat", genuine()));
+        let deliveries = queue.flush(&mut backend);
+        assert_eq!(
+            names(&deliveries),
+            ["accepted ann", "rejected bob", "accepted cat"]
+        );
+        assert!(queue.pending.is_empty());
+        assert_eq!(backend.scores.len(), 2);
+    }
+
+    #[test]
+    fn scores_wait_in_order_while_the_server_is_down() {
+        let mut backend = MemoryBackend {
+            offline: true,
+            ..Default::default()
+        };
+        let mut queue = ScoreQueue::default();
+        queue.push(submission("ann", claim(1)));
+        queue.push(submission("bob", claim(2)));
+        assert_eq!(names(&queue.flush(&mut backend)), ["held"]);
+        assert_eq!(queue.pending.len(), 2);
+        assert!(backend.top(10).is_err());
+
+        backend.offline = false;
+        assert_eq!(
+            names(&queue.flush(&mut backend)),
+            ["accepted ann", "accepted bob"]
+        );
+        assert!(queue.pending.is_empty());
+    }
+}
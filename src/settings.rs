@@ -0,0 +1,537 @@
+use bevy::{
+    prelude::*,
+    window::{PresentMode, PrimaryWindow, WindowMode},
+};
+use my_bevy_game::save::{self, Folder, Versioned};
+use my_bevy_game::{HEIGHT_SCREEN, WIDTH_SCREEN};
+use serde::{Deserialize, Serialize};
+
+use crate::leaderboard::{no_name_entry, LeaderboardScreen};
+use crate::powerups::PowerUps;
+use crate::profile::Profile;
+use crate::skins::{Skinned, Skins};
+use crate::theme::{self, Themes};
+use crate::{Game, Player};
+
+/// Window sizes offered, as multiples of the game's own size.
+const WINDOW_SCALES: [f32; 3] = [1.0, 1.5, 2.0];
+/// Keys a flap can be bound to.
+const BINDABLE: [KeyCode; 46] = [
+    KeyCode::Space,
+    KeyCode::Return,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::LShift,
+    KeyCode::RShift,
+    KeyCode::LControl,
+    KeyCode::RControl,
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+const DEFAULT_KEYS: [KeyCode; 2] = [KeyCode::Space, KeyCode::Up];
+const FILE: &str = "settings.ron";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Palette {
+    #[default]
+    Standard,
+    ColourBlind,
+}
+
+/// Preferences picked in the settings menu. They are kept on disk, or in the
+/// browser's local storage on the web, and applied before anything spawns.
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct Settings {
+    /// From 0 to 1. Volume and mute are picked and saved here for the sound
+    /// the game does not play yet.
+    pub volume: f32,
+    pub muted: bool,
+    pub vsync: bool,
+    pub fullscreen: bool,
+    pub window_scale: f32,
+    /// Theme to keep, or `None` to follow the time of day.
+    pub theme: Option<String>,
+    pub palette: Palette,
+    /// Names of the keys that flap the first and second player's birds.
+    pub flap_keys: [String; 2],
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            volume: 0.8,
+            muted: false,
+            vsync: true,
+            fullscreen: false,
+            window_scale: 1.0,
+            theme: None,
+            palette: Palette::Standard,
+            flap_keys: DEFAULT_KEYS.map(key_name),
+        }
+    }
+}
+
+fn key_name(key: KeyCode) -> String {
+    format!("{key:?}")
+}
+
+impl Settings {
+    /// Window size for the chosen scale.
+    pub fn resolution(&self) -> (f32, f32) {
+        (
+            WIDTH_SCREEN * self.window_scale,
+            HEIGHT_SCREEN * self.window_scale,
+        )
+    }
+
+    /// Key that flaps player `index`'s bird. Names that are not a bindable
+    /// key fall back to the default.
+    pub fn flap_key(&self, index: usize) -> KeyCode {
+        BINDABLE
+            .into_iter()
+            .find(|key| key_name(*key) == self.flap_keys[index])
+            .unwrap_or(DEFAULT_KEYS[index])
+    }
+
+    /// The saved settings, and what was wrong with any copies that could
+    /// not be read.
+    pub fn load() -> (Self, Vec<String>) {
+        let Some(storage) = save::platform_storage(Folder::Config) else {
+            return (Settings::default(), Vec::new());
+        };
+        let loaded = save::load(storage.as_ref(), FILE);
+        (loaded.data.unwrap_or_default(), loaded.problems)
+    }
+
+    pub fn save(&self) {
+        let Some(mut storage) = save::platform_storage(Folder::Config) else {
+            return;
+        };
+        if let Err(err) = save::save(storage.as_mut(), FILE, self) {
+            warn!("could not save settings: {err}");
+        }
+    }
+}
+
+impl Versioned for Settings {
+    const VERSION: u32 = 1;
+
+    fn migrate(version: u32, _text: &str) -> Result<Self, String> {
+        Err(format!("no settings schema {version}"))
+    }
+}
+
+/// Loads the settings before the app is built, since the window needs them,
+/// and reports a broken file once logging is up.
+pub struct SettingsPlugin {
+    pub settings: Settings,
+    problems: Vec<String>,
+}
+
+impl SettingsPlugin {
+    pub fn load() -> Self {
+        let (settings, problems) = Settings::load();
+        SettingsPlugin { settings, problems }
+    }
+}
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        for problem in &self.problems {
+            warn!("skipping unreadable settings: {problem}");
+        }
+        app.insert_resource(self.settings.clone())
+            .init_resource::<SettingsMenu>()
+            .add_startup_system(apply_settings.in_base_set(StartupSet::PreStartup))
+            .add_startup_system(spawn_menu)
+            .add_systems(
+                (
+                    toggle_menu.run_if(no_name_entry),
+                    rebind,
+                    navigate,
+                    update_menu,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Row {
+    Volume,
+    Mute,
+    VSync,
+    Fullscreen,
+    WindowScale,
+    Skin,
+    Theme,
+    Palette,
+    Flap(usize),
+    Leaderboard,
+}
+
+const ROWS: [Row; 11] = [
+    Row::Volume,
+    Row::Mute,
+    Row::VSync,
+    Row::Fullscreen,
+    Row::WindowScale,
+    Row::Skin,
+    Row::Theme,
+    Row::Palette,
+    Row::Flap(0),
+    Row::Flap(1),
+    Row::Leaderboard,
+];
+
+/// F1 opens the menu on the title screen or after a run; F1 or Escape closes
+/// and saves it. Up and down pick a row, left and right change it, and Enter
+/// on a flap row waits for the new key or opens the leaderboard.
+#[derive(Resource, Default)]
+pub struct SettingsMenu {
+    open: bool,
+    row: usize,
+    rebinding: bool,
+}
+
+#[derive(Component)]
+struct MenuRoot;
+
+#[derive(Component)]
+struct MenuText;
+
+/// Keeps the game's own keys away from the menu while it is open.
+pub fn menu_closed(menu: Res<SettingsMenu>) -> bool {
+    !menu.open
+}
+
+/// Points the theme and power-ups at the saved choices. The window was
+/// already made with them.
+fn apply_settings(
+    settings: Res<Settings>,
+    mut themes: ResMut<Themes>,
+    mut power_ups: ResMut<PowerUps>,
+) {
+    set_theme(&settings, &mut themes);
+    power_ups.colour_blind = settings.palette == Palette::ColourBlind;
+}
+
+fn set_theme(settings: &Settings, themes: &mut Themes) {
+    let index = settings
+        .theme
+        .as_ref()
+        .and_then(|name| themes.list.iter().position(|theme| theme.name == name));
+    match index {
+        Some(index) => {
+            themes.rotation.time_of_day = false;
+            themes.current = index;
+        }
+        None => {
+            themes.rotation.time_of_day = true;
+            theme::pick_by_time_of_day(themes);
+        }
+    }
+}
+
+fn set_window(settings: &Settings, windows: &mut Query<&mut Window, With<PrimaryWindow>>) {
+    for mut window in windows {
+        window.present_mode = if settings.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        };
+        window.mode = if settings.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        };
+        let (width, height) = settings.resolution();
+        if window.resolution.width() != width || window.resolution.height() != height {
+            window.resolution.set(width, height);
+        }
+    }
+}
+
+fn spawn_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::width(Val::Percent(100.0)),
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        top: Val::Px(0.0),
+                        bottom: Val::Px(0.0),
+                        ..default()
+                    },
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+                visibility: Visibility::Hidden,
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            MenuRoot,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 28.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                MenuText,
+            ));
+        });
+}
+
+fn toggle_menu(
+    keyboard_input: Res<Input<KeyCode>>,
+    game: Res<Game>,
+    settings: Res<Settings>,
+    mut menu: ResMut<SettingsMenu>,
+) {
+    menu.open = if menu.open {
+        let close = keyboard_input.just_pressed(KeyCode::F1)
+            || keyboard_input.just_pressed(KeyCode::Escape);
+        if menu.rebinding || !close {
+            return;
+        }
+        settings.save();
+        false
+    } else {
+        if !matches!(game.state, 0 | 3) || !keyboard_input.just_pressed(KeyCode::F1) {
+            return;
+        }
+        menu.row = 0;
+        true
+    };
+}
+
+/// Binds the next bindable key to the selected flap row. Escape keeps the
+/// old key.
+fn rebind(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut menu: ResMut<SettingsMenu>,
+    mut settings: ResMut<Settings>,
+    mut players: Query<&mut Player>,
+) {
+    if !menu.rebinding {
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        menu.rebinding = false;
+        return;
+    }
+    let Row::Flap(index) = ROWS[menu.row] else {
+        menu.rebinding = false;
+        return;
+    };
+    let Some(key) = BINDABLE
+        .into_iter()
+        .find(|key| keyboard_input.just_pressed(*key))
+    else {
+        return;
+    };
+    settings.flap_keys[index] = key_name(key);
+    menu.rebinding = false;
+    for mut player in &mut players {
+        if player.index == index {
+            player.key = key;
+        }
+    }
+}
+
+fn navigate(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut menu: ResMut<SettingsMenu>,
+    mut settings: ResMut<Settings>,
+    mut themes: ResMut<Themes>,
+    mut power_ups: ResMut<PowerUps>,
+    skins: Res<Skins>,
+    mut profile: ResMut<Profile>,
+    mut leaderboard: ResMut<LeaderboardScreen>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    mut birds: Query<(&mut Skinned, Option<&Player>)>,
+) {
+    if !menu.open || menu.rebinding {
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        menu.row = (menu.row + 1) % ROWS.len();
+    }
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        menu.row = (menu.row + ROWS.len() - 1) % ROWS.len();
+    }
+    let row = ROWS[menu.row];
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        match row {
+            Row::Flap(_) => menu.rebinding = true,
+            Row::Leaderboard => {
+                settings.save();
+                menu.open = false;
+                leaderboard.open = true;
+            }
+            _ => {}
+        }
+        return;
+    }
+    let step: isize = if keyboard_input.just_pressed(KeyCode::Right) {
+        1
+    } else if keyboard_input.just_pressed(KeyCode::Left) {
+        -1
+    } else {
+        return;
+    };
+    match row {
+        Row::Volume => {
+            settings.volume =
+                ((settings.volume * 10.0).round() + step as f32).clamp(0.0, 10.0) / 10.0
+        }
+        Row::Mute => settings.muted = !settings.muted,
+        Row::VSync => settings.vsync = !settings.vsync,
+        Row::Fullscreen => settings.fullscreen = !settings.fullscreen,
+        Row::WindowScale => {
+            let current = WINDOW_SCALES
+                .iter()
+                .position(|scale| *scale == settings.window_scale)
+                .unwrap_or(0);
+            let next = (current as isize + step).rem_euclid(WINDOW_SCALES.len() as isize);
+            settings.window_scale = WINDOW_SCALES[next as usize];
+        }
+        Row::Skin => {
+            let skin = skins.cycle(&profile, step);
+            profile.skin = skin.to_string();
+            profile.save();
+            for (mut skinned, player) in &mut birds {
+                if player.is_none_or(|player| player.index == 0) {
+                    skinned.set(skin);
+                }
+            }
+        }
+        Row::Theme => {
+            // Following the time of day comes before the first theme.
+            let names: Vec<Option<&str>> = std::iter::once(None)
+                .chain(themes.list.iter().map(|theme| Some(theme.name)))
+                .collect();
+            let current = names
+                .iter()
+                .position(|name| *name == settings.theme.as_deref())
+                .unwrap_or(0);
+            let next = (current as isize + step).rem_euclid(names.len() as isize);
+            settings.theme = names[next as usize].map(str::to_string);
+            set_theme(&settings, &mut themes);
+        }
+        Row::Palette => {
+            settings.palette = match settings.palette {
+                Palette::Standard => Palette::ColourBlind,
+                Palette::ColourBlind => Palette::Standard,
+            };
+            power_ups.colour_blind = settings.palette == Palette::ColourBlind;
+        }
+        Row::Flap(_) | Row::Leaderboard => return,
+    }
+    if matches!(row, Row::VSync | Row::Fullscreen | Row::WindowScale) {
+        set_window(&settings, &mut windows);
+    }
+}
+
+fn update_menu(
+    menu: Res<SettingsMenu>,
+    settings: Res<Settings>,
+    skins: Res<Skins>,
+    profile: Res<Profile>,
+    mut roots: Query<&mut Visibility, With<MenuRoot>>,
+    mut text: Query<&mut Text, With<MenuText>>,
+) {
+    if menu.is_changed() {
+        for mut visibility in &mut roots {
+            *visibility = if menu.open {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+    if !menu.open || !(menu.is_changed() || settings.is_changed() || profile.is_changed()) {
+        return;
+    }
+    let on_off = |on: bool| if on { "on" } else { "off" };
+    let mut value = "Settings\n\n".to_string();
+    for (index, row) in ROWS.into_iter().enumerate() {
+        let line = match row {
+            Row::Volume => format!("Volume: {:.0}%", settings.volume * 100.0),
+            Row::Mute => format!("Mute: {}", on_off(settings.muted)),
+            Row::VSync => format!("VSync: {}", on_off(settings.vsync)),
+            Row::Fullscreen => format!("Fullscreen: {}", on_off(settings.fullscreen)),
+            Row::WindowScale => format!("Window scale: {}x", settings.window_scale),
+            Row::Skin => format!("Skin: {}", skins.get(&profile.skin).name),
+            Row::Theme => format!(
+                "Theme: {}",
+                settings.theme.as_deref().unwrap_or("time of day")
+            ),
+            Row::Palette => format!(
+                "Palette: {}",
+                match settings.palette {
+                    Palette::Standard => "standard",
+                    Palette::ColourBlind => "colour-blind",
+                }
+            ),
+            Row::Flap(index) if menu.rebinding && ROWS[menu.row] == row => {
+                format!("P{} flap: press a key", index + 1)
+            }
+            Row::Flap(index) => format!("P{} flap: {:?}", index + 1, settings.flap_key(index)),
+            Row::Leaderboard => "Leaderboard".to_string(),
+        };
+        let marker = if index == menu.row { "> " } else { "  " };
+        value.push_str(marker);
+        value.push_str(&line);
+        value.push('\n');
+    }
+    value.push_str("\nUp/Down pick, Left/Right change, Enter rebind, F1 close");
+    for mut text in &mut text {
+        text.sections[0].value = value.clone();
+    }
+}
@@ -0,0 +1,455 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::course::{boxes_overlap, pipe_parts, Behaviour, Difficulty, PipeGenerator, PipeSpec};
+use crate::{
+    DISTANCE_BETWEEN_UP_DOWN_PIPES, DISTANCE_X_BETWEEN_PIPE, GROUND_HEIGHT, HEIGHT_PIPE,
+    HEIGHT_SCREEN, SCROLL_SPEED, WIDTH_PIPE, WIDTH_SCREEN,
+};
+
+/// Length of one simulation tick, the same as the game's fixed timestep.
+pub const TICK_SECS: f32 = 1.0 / 60.0;
+/// Upward speed a flap gives a bird.
+pub const FLAP_SPEED: f32 = 200.0;
+/// Speed a bird is knocked down with when it hits a pipe.
+pub const CRASH_SPEED: f32 = -300.0;
+/// Speed lost to gravity every tick.
+pub const GRAVITY: f32 = -5.0;
+/// Hitbox of the default skin.
+pub const BIRD_SIZE: Vec2 = Vec2::new(26.0, 26.0);
+/// Hitbox of the skins drawn from the art atlas.
+pub const ATLAS_BIRD_SIZE: Vec2 = Vec2::new(24.0, 22.0);
+/// Every hitbox a skin in the game has.
+pub const SKIN_HITBOXES: [Vec2; 2] = [BIRD_SIZE, ATLAS_BIRD_SIZE];
+
+/// Pipes enter at the right edge of the default window, like in the game.
+const ENTER_X: f32 = WIDTH_SCREEN / 2.0 + DISTANCE_X_BETWEEN_PIPE;
+const EXIT_X: f32 = -WIDTH_SCREEN / 2.0 - WIDTH_PIPE;
+
+/// The physics a race is flown with. The default is the game's; tools that
+/// check how a change would play can fly races with other values.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rules {
+    /// Speed lost to gravity every tick.
+    pub gravity: f32,
+    /// Upward speed a flap gives a bird.
+    pub flap_speed: f32,
+    /// Height of the opening between a pair of pipes at rest.
+    pub opening: f32,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            gravity: GRAVITY,
+            flap_speed: FLAP_SPEED,
+            opening: DISTANCE_BETWEEN_UP_DOWN_PIPES,
+        }
+    }
+}
+
+/// What brought a bird down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cause {
+    UpperPipe,
+    LowerPipe,
+    Ground,
+    /// Hit a pipe while pressed against the top of the screen.
+    Ceiling,
+}
+
+/// Where a bird ended up after a tick of falling.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Landing {
+    Air,
+    /// Bumped its head on the upper ground strip.
+    Ceiling,
+    Ground,
+}
+
+/// One tick of gravity for a bird `half_height` tall. The game's birds and
+/// the simulated ones both fall through here.
+pub fn fall(y: &mut f32, speed: &mut f32, acc: f32, half_height: f32, dt: f32) -> Landing {
+    *speed += acc;
+    *y += *speed * dt;
+    let ceiling = HEIGHT_SCREEN / 2.0 - GROUND_HEIGHT - half_height;
+    let ground = -HEIGHT_SCREEN / 2.0 + GROUND_HEIGHT + half_height;
+    if *y >= ceiling {
+        *y = ceiling;
+        *speed = -3.0;
+        return Landing::Ceiling;
+    }
+    if *y <= ground {
+        Landing::Ground
+    } else {
+        Landing::Air
+    }
+}
+
+/// Centre, half size and rotation of one pipe part centred on `centre`.
+pub fn pipe_box(centre: Vec2, angle: f32) -> (Vec2, Vec2, f32) {
+    (centre, Vec2::new(WIDTH_PIPE, HEIGHT_PIPE) / 2.0, angle)
+}
+
+/// Whether an upright bird of `size` centred on `centre` touches any of
+/// `parts`, as given by `pipe_box`.
+pub fn hits_pipe(
+    centre: Vec2,
+    size: Vec2,
+    parts: impl IntoIterator<Item = (Vec2, Vec2, f32)>,
+) -> bool {
+    let bird = (centre, size / 2.0, 0.0);
+    parts.into_iter().any(|part| boxes_overlap(bird, part))
+}
+
+#[derive(Clone, Debug)]
+pub struct SimBird {
+    pub x: f32,
+    pub y: f32,
+    pub speed: f32,
+    pub score: i32,
+    /// Tick on which the bird hit something or the ground.
+    pub down_at: Option<u32>,
+    /// What it hit, unless it was knocked out.
+    pub cause: Option<Cause>,
+    /// Whether the bird lies on the ground.
+    pub grounded: bool,
+    /// Size of its hitbox.
+    pub size: Vec2,
+    flap: bool,
+    /// Whether it touched the ceiling this tick.
+    ceiling: bool,
+}
+
+impl SimBird {
+    pub fn alive(&self) -> bool {
+        self.down_at.is_none()
+    }
+}
+
+/// A pipe pair as the rules see it. The game keeps one in each of its pipes
+/// and moves it through here, so both place and score pairs the same way.
+#[derive(Clone, Debug)]
+pub struct SimPipe {
+    pub x: f32,
+    pub idx: i32,
+    /// Height of the middle of the opening.
+    pub gap: f32,
+    base_gap: f32,
+    behaviour: Behaviour,
+    /// How much wider than the game's the opening is on each side.
+    widen: f32,
+    /// How much the opening is widened on each side.
+    pub dh: f32,
+    pub angle: f32,
+}
+
+impl SimPipe {
+    /// Pair `idx` as `spec` lays it out, at `x`.
+    pub fn new(x: f32, idx: i32, spec: &PipeSpec, widen: f32) -> Self {
+        let gap = spec.top_below + DISTANCE_BETWEEN_UP_DOWN_PIPES / 2.0;
+        let mut pipe = SimPipe {
+            x,
+            idx,
+            gap,
+            base_gap: gap,
+            behaviour: spec.behaviour,
+            widen,
+            dh: widen,
+            angle: 0.0,
+        };
+        pipe.place(x);
+        pipe
+    }
+
+    /// Moves the pair to `x`. How far a moving pair has got through its
+    /// motion follows from how far it has come since entering the default
+    /// window, so it looks the same wherever and whenever it was laid out.
+    pub fn place(&mut self, x: f32) {
+        self.x = x;
+        let age = (ENTER_X - x) / SCROLL_SPEED;
+        let (dy, dh, angle) = self.behaviour.sample(age);
+        self.gap = self.base_gap + dy;
+        self.dh = dh + self.widen;
+        self.angle = angle;
+    }
+
+    /// Centre, half size and rotation of both parts.
+    pub fn boxes(&self) -> [(Vec2, Vec2, f32); 2] {
+        pipe_parts(Vec2::new(self.x, self.gap), self.dh, self.angle)
+            .map(|centre| pipe_box(centre, self.angle))
+    }
+
+    /// Which part an upright bird of `size` centred on `centre` touches.
+    pub fn hit(&self, centre: Vec2, size: Vec2) -> Option<Cause> {
+        let [upper, lower] = self.boxes();
+        if hits_pipe(centre, size, [upper]) {
+            Some(Cause::UpperPipe)
+        } else if hits_pipe(centre, size, [lower]) {
+            Some(Cause::LowerPipe)
+        } else {
+            None
+        }
+    }
+}
+
+/// Score of a bird at `x` that had `score`: the last pair it is past.
+pub fn score_past<'a>(score: i32, x: f32, pipes: impl IntoIterator<Item = &'a SimPipe>) -> i32 {
+    pipes
+        .into_iter()
+        .filter(|pipe| pipe.x < x)
+        .fold(score, |score, pipe| score.max(pipe.idx))
+}
+
+/// One endless run on a seeded course, stepped a tick at a time without any
+/// rendering. Any number of birds fly it side by side; they never touch each
+/// other. Cloning a race snapshots it.
+#[derive(Clone)]
+pub struct Race {
+    generator: PipeGenerator,
+    rules: Rules,
+    pub pipes: VecDeque<SimPipe>,
+    pub birds: Vec<SimBird>,
+    /// Ticks since the run started.
+    pub tick: u32,
+    pub started: bool,
+    next_idx: i32,
+    tail_x: Option<f32>,
+}
+
+impl Race {
+    pub fn new(seed: u64, difficulty: Difficulty) -> Self {
+        Race::with_rules(seed, difficulty, Rules::default())
+    }
+
+    pub fn with_rules(seed: u64, difficulty: Difficulty, rules: Rules) -> Self {
+        let mut race = Race {
+            generator: PipeGenerator::new(difficulty, seed),
+            rules,
+            pipes: VecDeque::new(),
+            birds: Vec::new(),
+            tick: 0,
+            started: false,
+            next_idx: 1,
+            tail_x: None,
+        };
+        race.spawn_pipes();
+        race
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.generator.seed()
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.generator.difficulty
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// How much wider than the game's each opening is on each side.
+    fn widen(&self) -> f32 {
+        (self.rules.opening - DISTANCE_BETWEEN_UP_DOWN_PIPES) / 2.0
+    }
+
+    /// Adds a bird with the default skin's hitbox at `x` and returns its
+    /// index.
+    pub fn add_bird(&mut self, x: f32) -> usize {
+        self.add_sized_bird(x, BIRD_SIZE)
+    }
+
+    /// Adds a bird at `x` whose hitbox is `size` and returns its index.
+    pub fn add_sized_bird(&mut self, x: f32, size: Vec2) -> usize {
+        self.birds.push(SimBird {
+            x,
+            y: 0.0,
+            speed: self.rules.flap_speed,
+            score: 0,
+            down_at: None,
+            cause: None,
+            grounded: false,
+            size,
+            flap: false,
+            ceiling: false,
+        });
+        self.birds.len() - 1
+    }
+
+    /// Starts the run without waiting for a flap.
+    pub fn start(&mut self) {
+        self.started = true;
+    }
+
+    /// Makes `bird` flap on the next step. The first flap starts the run.
+    pub fn flap(&mut self, bird: usize) {
+        if let Some(bird) = self.birds.get_mut(bird) {
+            bird.flap = true;
+        }
+    }
+
+    /// Knocks `bird` out of the run, e.g. because its player left.
+    pub fn knock_out(&mut self, bird: usize) {
+        let tick = self.tick;
+        if let Some(bird) = self.birds.get_mut(bird) {
+            bird.down_at.get_or_insert(tick);
+            bird.speed = bird.speed.min(CRASH_SPEED);
+        }
+    }
+
+    /// Whether anyone is still flying.
+    pub fn flying(&self) -> bool {
+        self.birds.iter().any(SimBird::alive)
+    }
+
+    /// The run is over once every bird lies on the ground.
+    pub fn over(&self) -> bool {
+        self.started && self.birds.iter().all(|bird| bird.grounded)
+    }
+
+    /// Advances the run by one tick, in the same order the game runs its
+    /// systems: flaps, scrolling, falling, collisions, then scoring.
+    pub fn step(&mut self) {
+        if !self.started && self.birds.iter().any(|bird| bird.flap) {
+            self.started = true;
+        }
+        if !self.started {
+            return;
+        }
+        let playing = self.flying();
+        for bird in &mut self.birds {
+            if std::mem::take(&mut bird.flap) && bird.alive() {
+                bird.speed = self.rules.flap_speed;
+            }
+        }
+        if playing {
+            self.scroll();
+        }
+        self.fall();
+        if playing {
+            self.collide();
+            self.score();
+            self.tick += 1;
+        }
+    }
+
+    fn spawn_pipes(&mut self) {
+        loop {
+            let x = self.tail_x.map_or(DISTANCE_X_BETWEEN_PIPE, |tail| {
+                tail + DISTANCE_X_BETWEEN_PIPE
+            });
+            if x > ENTER_X {
+                break;
+            }
+            let spec = self.generator.next(self.next_idx);
+            let pipe = SimPipe::new(x, self.next_idx, &spec, self.widen());
+            self.pipes.push_back(pipe);
+            self.next_idx += 1;
+            self.tail_x = Some(x);
+        }
+    }
+
+    fn scroll(&mut self) {
+        let dx = SCROLL_SPEED * TICK_SECS;
+        if let Some(tail) = &mut self.tail_x {
+            *tail -= dx;
+        }
+        for pipe in &mut self.pipes {
+            pipe.place(pipe.x - dx);
+        }
+        while self.pipes.front().is_some_and(|pipe| pipe.x < EXIT_X) {
+            self.pipes.pop_front();
+        }
+        self.spawn_pipes();
+    }
+
+    fn fall(&mut self) {
+        let tick = self.tick;
+        for bird in &mut self.birds {
+            if bird.grounded {
+                continue;
+            }
+            let landing = fall(
+                &mut bird.y,
+                &mut bird.speed,
+                self.rules.gravity,
+                bird.size.y / 2.0,
+                TICK_SECS,
+            );
+            bird.ceiling = landing == Landing::Ceiling;
+            if landing == Landing::Ground {
+                bird.grounded = true;
+                if bird.alive() {
+                    bird.down_at = Some(tick);
+                    bird.cause = Some(Cause::Ground);
+                }
+            }
+        }
+    }
+
+    fn collide(&mut self) {
+        let tick = self.tick;
+        for bird in self.birds.iter_mut().filter(|bird| bird.alive()) {
+            let centre = Vec2::new(bird.x, bird.y);
+            let Some(cause) = self
+                .pipes
+                .iter()
+                .find_map(|pipe| pipe.hit(centre, bird.size))
+            else {
+                continue;
+            };
+            bird.speed = CRASH_SPEED;
+            bird.down_at = Some(tick);
+            bird.cause = Some(if bird.ceiling { Cause::Ceiling } else { cause });
+        }
+    }
+
+    fn score(&mut self) {
+        for bird in self.birds.iter_mut().filter(|bird| bird.alive()) {
+            bird.score = score_past(bird.score, bird.x, &self.pipes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+
+    /// A still pair at `x = 0` whose opening is centred on `y = 0`.
+    fn pair() -> SimPipe {
+        let spec = PipeSpec {
+            top_below: -DISTANCE_BETWEEN_UP_DOWN_PIPES / 2.0,
+            behaviour: Behaviour::Static,
+        };
+        SimPipe::new(0.0, 1, &spec, 0.0)
+    }
+
+    #[test]
+    fn an_upright_pair_is_hit_above_and_below_the_opening() {
+        let pipe = pair();
+        let edge = DISTANCE_BETWEEN_UP_DOWN_PIPES / 2.0;
+        assert_eq!(pipe.hit(Vec2::ZERO, BIRD_SIZE), None);
+        let above = Vec2::new(0.0, edge + 1.0);
+        assert_eq!(pipe.hit(above, BIRD_SIZE), Some(Cause::UpperPipe));
+        let below = Vec2::new(0.0, -edge - 1.0);
+        assert_eq!(pipe.hit(below, BIRD_SIZE), Some(Cause::LowerPipe));
+    }
+
+    #[test]
+    fn a_turned_pair_is_hit_where_its_parts_swung_to() {
+        let mut pipe = pair();
+        pipe.angle = FRAC_PI_2;
+        let edge = DISTANCE_BETWEEN_UP_DOWN_PIPES / 2.0;
+        // The parts now lie to either side, leaving above and below open.
+        assert_eq!(pipe.hit(Vec2::new(0.0, edge + 1.0), BIRD_SIZE), None);
+        assert!(pipe.hit(Vec2::new(edge + 1.0, 0.0), BIRD_SIZE).is_some());
+        assert!(pipe.hit(Vec2::new(-edge - 1.0, 0.0), BIRD_SIZE).is_some());
+    }
+}
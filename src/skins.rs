@@ -0,0 +1,264 @@
+use bevy::{prelude::*, utils::HashMap};
+use my_bevy_game::sim::{ATLAS_BIRD_SIZE, BIRD_SIZE};
+
+use crate::atlas::{Art, ArtLoader};
+use crate::profile::Profile;
+use crate::theme::{BaseTint, Themes};
+use crate::{AnimationIndices, AnimationTimer};
+
+/// Where the animation frames of a skin come from.
+pub enum Frames {
+    /// An image sliced into equally sized cells, read row by row.
+    Grid {
+        image: &'static str,
+        tile: Vec2,
+        columns: usize,
+        rows: usize,
+    },
+    /// Named regions of the shared atlas, in animation order.
+    Regions(&'static [&'static str]),
+}
+
+impl Frames {
+    fn len(&self) -> usize {
+        match self {
+            Frames::Grid { columns, rows, .. } => columns * rows,
+            Frames::Regions(names) => names.len(),
+        }
+    }
+}
+
+pub struct Skin {
+    pub name: &'static str,
+    pub frames: Frames,
+    pub frame_secs: f32,
+    /// Size of the box used for collisions, usually a little smaller than a
+    /// frame. One of [`SKIN_HITBOXES`](my_bevy_game::sim::SKIN_HITBOXES), or
+    /// its runs will not verify.
+    pub hitbox: Vec2,
+    pub tint: Option<Color>,
+    /// Score a single run has to reach before the skin becomes available.
+    pub unlock_score: i32,
+}
+
+impl Skin {
+    pub fn tint(&self) -> Color {
+        self.tint.unwrap_or(Color::WHITE)
+    }
+}
+
+#[derive(Resource)]
+pub struct Skins {
+    pub list: Vec<Skin>,
+}
+
+impl Skins {
+    pub fn get(&self, name: &str) -> &Skin {
+        self.list
+            .iter()
+            .find(|skin| skin.name == name)
+            .unwrap_or(&self.list[0])
+    }
+
+    /// Name of the unlocked skin `step` places away from the profile's current one.
+    pub fn cycle(&self, profile: &Profile, step: isize) -> &'static str {
+        let unlocked: Vec<&Skin> = self
+            .list
+            .iter()
+            .filter(|skin| profile.unlocked_skins.iter().any(|name| name == skin.name))
+            .collect();
+        if unlocked.is_empty() {
+            return self.list[0].name;
+        }
+        let current = unlocked
+            .iter()
+            .position(|skin| skin.name == profile.skin)
+            .unwrap_or(0) as isize;
+        let next = (current + step).rem_euclid(unlocked.len() as isize);
+        unlocked[next as usize].name
+    }
+}
+
+impl Default for Skins {
+    fn default() -> Self {
+        const ATLAS_BIRD: &[&str] = &["bird1", "bird2", "bird3"];
+        Skins {
+            list: vec![
+                Skin {
+                    name: "yellow",
+                    frames: Frames::Grid {
+                        image: "image/bird.png",
+                        tile: Vec2::new(36.0, 26.0),
+                        columns: 1,
+                        rows: 3,
+                    },
+                    frame_secs: 0.1,
+                    hitbox: BIRD_SIZE,
+                    tint: None,
+                    unlock_score: 0,
+                },
+                Skin {
+                    name: "flappy",
+                    frames: Frames::Regions(ATLAS_BIRD),
+                    frame_secs: 0.08,
+                    hitbox: ATLAS_BIRD_SIZE,
+                    tint: None,
+                    unlock_score: 10,
+                },
+                Skin {
+                    name: "ruby",
+                    frames: Frames::Grid {
+                        image: "image/bird.png",
+                        tile: Vec2::new(36.0, 26.0),
+                        columns: 1,
+                        rows: 3,
+                    },
+                    frame_secs: 0.1,
+                    hitbox: BIRD_SIZE,
+                    tint: Some(Color::rgb(1.0, 0.55, 0.55)),
+                    unlock_score: 25,
+                },
+                Skin {
+                    name: "frost",
+                    frames: Frames::Regions(ATLAS_BIRD),
+                    frame_secs: 0.12,
+                    hitbox: ATLAS_BIRD_SIZE,
+                    tint: Some(Color::rgb(0.6, 0.85, 1.0)),
+                    unlock_score: 50,
+                },
+            ],
+        }
+    }
+}
+
+/// Collision box of a bird, taken from its skin.
+#[derive(Component, Clone, Copy, Deref)]
+pub struct Hitbox(pub Vec2);
+
+/// Requests a skin for a bird. The atlas is swapped in once its art is loaded.
+#[derive(Component)]
+pub struct Skinned {
+    skin: &'static str,
+    applied: bool,
+}
+
+impl Skinned {
+    pub fn new(skin: &'static str) -> Self {
+        Skinned {
+            skin,
+            applied: false,
+        }
+    }
+
+    pub fn set(&mut self, skin: &'static str) {
+        self.skin = skin;
+        self.applied = false;
+    }
+}
+
+pub struct SkinsPlugin;
+
+impl Plugin for SkinsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Skins>()
+            .add_startup_system(unlock_free_skins.in_base_set(StartupSet::PreStartup))
+            .add_system(apply_skins);
+    }
+}
+
+fn unlock_free_skins(skins: Res<Skins>, mut profile: ResMut<Profile>) {
+    unlock_for_score(0, &skins, &mut profile);
+    if !profile.unlocked_skins.contains(&profile.skin) {
+        profile.skin = skins.list[0].name.to_string();
+    }
+}
+
+/// Unlocks every skin whose milestone `score` reaches and saves the profile if
+/// anything changed.
+pub fn unlock_for_score(score: i32, skins: &Skins, profile: &mut Profile) {
+    let mut unlocked = false;
+    for skin in skins.list.iter().filter(|skin| skin.unlock_score <= score) {
+        if !profile.unlocked_skins.iter().any(|name| name == skin.name) {
+            profile.unlocked_skins.push(skin.name.to_string());
+            if score > 0 {
+                info!("unlocked skin {}", skin.name);
+            }
+            unlocked = true;
+        }
+    }
+    if unlocked {
+        profile.save();
+    }
+}
+
+fn build_atlas(skin: &Skin, art: &ArtLoader, asset_server: &AssetServer) -> Option<TextureAtlas> {
+    match skin.frames {
+        Frames::Grid {
+            image,
+            tile,
+            columns,
+            rows,
+        } => Some(TextureAtlas::from_grid(
+            asset_server.load(image),
+            tile,
+            columns,
+            rows,
+            None,
+            None,
+        )),
+        Frames::Regions(names) => {
+            let frames: Vec<_> = names
+                .iter()
+                .map(|name| art.resolve(Art::Region(name)))
+                .collect::<Option<_>>()?;
+            let texture = frames.first()?.texture.clone();
+            let mut atlas = TextureAtlas::new_empty(texture.clone(), art.image_size(&texture)?);
+            for frame in frames {
+                atlas.add_texture(frame.rect?);
+            }
+            Some(atlas)
+        }
+    }
+}
+
+fn apply_skins(
+    mut commands: Commands,
+    skins: Res<Skins>,
+    themes: Res<Themes>,
+    art: ArtLoader,
+    asset_server: Res<AssetServer>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+    mut cache: Local<HashMap<&'static str, Handle<TextureAtlas>>>,
+    mut birds: Query<(
+        Entity,
+        &mut Skinned,
+        &mut Handle<TextureAtlas>,
+        &mut TextureAtlasSprite,
+    )>,
+) {
+    for (entity, mut skinned, mut atlas, mut sprite) in &mut birds {
+        if skinned.applied {
+            continue;
+        }
+        let skin = skins.get(skinned.skin);
+        if !cache.contains_key(skin.name) {
+            let Some(built) = build_atlas(skin, &art, &asset_server) else {
+                continue;
+            };
+            cache.insert(skin.name, atlases.add(built));
+        }
+        *atlas = cache[skin.name].clone();
+        sprite.index = 0;
+        sprite.color = themes.theme().bird * skin.tint().as_rgba_f32();
+        commands.entity(entity).insert((
+            AnimationIndices {
+                first: 0,
+                last: skin.frames.len() - 1,
+            },
+            AnimationTimer(Timer::from_seconds(skin.frame_secs, TimerMode::Repeating)),
+            Hitbox(skin.hitbox),
+            BaseTint(skin.tint()),
+        ));
+        skinned.applied = true;
+    }
+}
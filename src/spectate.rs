@@ -0,0 +1,133 @@
+//! What the game publishes to spectators: one JSON text frame per WebSocket
+//! message. A client first gets a `hello` describing the world, then a
+//! `tick` on every simulation tick. Every frame carries `version`; clients
+//! should ignore frames whose version they do not know.
+//!
+//! ```json
+//! {"version":1,"type":"hello","width":736.0,"height":576.0,"ground":121.0,...}
+//! {"version":1,"type":"tick","tick":42,"state":"flying","score":3,"birds":[...],"pipes":[...]}
+//! ```
+//!
+//! Coordinates are world units with the origin in the middle of the screen
+//! and y pointing up.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    DISTANCE_BETWEEN_UP_DOWN_PIPES, GROUND_HEIGHT, HEIGHT_PIPE, HEIGHT_SCREEN, WIDTH_PIPE,
+    WIDTH_SCREEN,
+};
+
+/// Bumped whenever a frame changes in a way old clients would misread.
+pub const VERSION: u32 = 1;
+pub const DEFAULT_ADDR: &str = "127.0.0.1:7879";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Frame {
+    pub version: u32,
+    #[serde(flatten)]
+    pub body: Body,
+}
+
+impl Frame {
+    pub fn new(body: Body) -> Self {
+        Frame {
+            version: VERSION,
+            body,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("frames always serialize")
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Body {
+    Hello(Hello),
+    Tick(Tick),
+}
+
+/// Sizes of the world, sent once on connect.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Hello {
+    pub width: f32,
+    pub height: f32,
+    /// Height of the ground strip at the top and bottom of the screen.
+    pub ground: f32,
+    pub pipe_width: f32,
+    pub pipe_height: f32,
+}
+
+impl Default for Hello {
+    fn default() -> Self {
+        Hello {
+            width: WIDTH_SCREEN,
+            height: HEIGHT_SCREEN,
+            ground: GROUND_HEIGHT,
+            pipe_width: WIDTH_PIPE,
+            pipe_height: HEIGHT_PIPE,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Tick {
+    /// Ticks since the first flap of the run.
+    pub tick: u32,
+    pub state: RunState,
+    pub score: i32,
+    pub birds: Vec<Bird>,
+    pub pipes: Vec<Pipe>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunState {
+    Waiting,
+    Flying,
+    Falling,
+    Dead,
+    Finished,
+    Editing,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Bird {
+    /// Which local player flies it; none for ghosts and remote birds.
+    pub player: Option<usize>,
+    pub ghost: bool,
+    pub x: f32,
+    pub y: f32,
+    /// Vertical speed, up is positive.
+    pub velocity: f32,
+    /// Radians, counter-clockwise.
+    pub rotation: f32,
+    pub score: i32,
+    pub alive: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Pipe {
+    pub idx: i32,
+    pub x: f32,
+    /// Middle of the opening.
+    pub gap: f32,
+    /// Height of the opening.
+    pub gap_height: f32,
+    /// Radians the pair is swung around the middle of its opening.
+    pub angle: f32,
+}
+
+impl Pipe {
+    pub fn new(idx: i32, x: f32, gap: f32, dh: f32, angle: f32) -> Self {
+        Pipe {
+            idx,
+            x,
+            gap,
+            gap_height: DISTANCE_BETWEEN_UP_DOWN_PIPES + 2.0 * dh,
+            angle,
+        }
+    }
+}
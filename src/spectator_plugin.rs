@@ -0,0 +1,170 @@
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+use bevy::prelude::*;
+use my_bevy_game::spectate::{self, Body, Frame, Hello, RunState, Tick};
+use tungstenite::{protocol::WebSocketConfig, Message, WebSocket};
+
+use crate::cli::Options;
+use crate::ghost::Ghost;
+use crate::pool::Pool;
+use crate::{advance_tick, Bird, Game, Pipe, Player};
+
+/// Spectators connected over WebSocket, when the game was started with
+/// `--spectate [address]`. Connections are accepted on their own thread and
+/// handed over here once the handshake is done.
+#[derive(Resource)]
+struct Spectators {
+    joined: Arc<Mutex<Vec<WebSocket<TcpStream>>>>,
+    watching: Vec<WebSocket<TcpStream>>,
+}
+
+pub struct SpectatorPlugin;
+
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        let Some(addr) = app.world.resource::<Options>().spectate.clone() else {
+            return;
+        };
+        let listener = match TcpListener::bind(&addr) {
+            Ok(listener) => listener,
+            Err(err) => {
+                error!("could not publish spectator stream on {addr}: {err}");
+                return;
+            }
+        };
+        info!("spectators can watch on ws://{addr}");
+        let joined = Arc::new(Mutex::new(Vec::new()));
+        let queue = joined.clone();
+        std::thread::spawn(move || accept(listener, queue));
+        app.insert_resource(Spectators {
+            joined,
+            watching: Vec::new(),
+        })
+        .add_system(
+            publish
+                .after(advance_tick)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    }
+}
+
+fn accept(listener: TcpListener, joined: Arc<Mutex<Vec<WebSocket<TcpStream>>>>) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!("spectator could not connect: {err}");
+                continue;
+            }
+        };
+        let peer = stream.peer_addr().ok();
+        // A spectator that falls this far behind is dropped.
+        let config = WebSocketConfig {
+            max_write_buffer_size: 1 << 20,
+            ..default()
+        };
+        let mut socket = match tungstenite::accept_with_config(stream, Some(config)) {
+            Ok(socket) => socket,
+            Err(err) => {
+                warn!("spectator handshake failed: {err}");
+                continue;
+            }
+        };
+        let hello = Frame::new(Body::Hello(Hello::default())).to_json();
+        // From here on the game thread writes, and must never wait on a
+        // spectator that stopped reading.
+        let ready = socket
+            .send(Message::Text(hello))
+            .map_err(|err| err.to_string())
+            .and_then(|_| {
+                socket
+                    .get_ref()
+                    .set_nonblocking(true)
+                    .map_err(|err| err.to_string())
+            });
+        match ready {
+            Ok(()) => {
+                info!("spectator {peer:?} joined");
+                joined.lock().unwrap().push(socket);
+            }
+            Err(err) => warn!("spectator {peer:?} dropped: {err}"),
+        }
+    }
+}
+
+fn run_state(state: i32) -> RunState {
+    match state {
+        0 => RunState::Waiting,
+        1 => RunState::Flying,
+        2 => RunState::Falling,
+        3 => RunState::Dead,
+        4 => RunState::Finished,
+        _ => RunState::Editing,
+    }
+}
+
+/// Sends every spectator where everything is after this tick.
+fn publish(
+    mut spectators: ResMut<Spectators>,
+    game: Res<Game>,
+    pipes: Res<Pool<Pipe>>,
+    birds: Query<(&Bird, &Transform, Option<&Player>, Option<&Ghost>)>,
+) {
+    let joined = std::mem::take(&mut *spectators.joined.lock().unwrap());
+    spectators.watching.extend(joined);
+    if spectators.watching.is_empty() {
+        return;
+    }
+    let tick = Tick {
+        tick: game.tick,
+        state: run_state(game.state),
+        score: game.score,
+        birds: birds
+            .iter()
+            .map(|(bird, transform, player, ghost)| spectate::Bird {
+                player: player.map(|player| player.index),
+                ghost: ghost.is_some(),
+                x: transform.translation.x,
+                y: transform.translation.y,
+                velocity: bird.speed,
+                rotation: transform.rotation.to_euler(EulerRot::ZYX).0,
+                score: bird.score,
+                alive: bird.alive(),
+            })
+            .collect(),
+        pipes: pipes
+            .live()
+            .map(|slot| {
+                let pipe = &slot.item.sim;
+                spectate::Pipe::new(pipe.idx, slot.x, pipe.gap, pipe.dh, pipe.angle)
+            })
+            .collect(),
+    };
+    let text = Frame::new(Body::Tick(tick)).to_json();
+    spectators.watching.retain_mut(|socket| {
+        // Answers pings and notices spectators that went away.
+        loop {
+            match socket.read() {
+                Ok(_) => continue,
+                Err(tungstenite::Error::Io(err))
+                    if err.kind() == std::io::ErrorKind::WouldBlock =>
+                {
+                    break
+                }
+                Err(_) => return false,
+            }
+        }
+        match socket.send(Message::Text(text.clone())) {
+            Ok(()) => true,
+            // Stays queued in the socket until the spectator catches up.
+            Err(tungstenite::Error::Io(err)) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                true
+            }
+            Err(err) => {
+                info!("spectator left: {err}");
+                false
+            }
+        }
+    });
+}
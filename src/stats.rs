@@ -0,0 +1,504 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use bevy::prelude::*;
+use my_bevy_game::save::{self, Folder, Versioned};
+use my_bevy_game::sim::{Cause, TICK_SECS};
+use serde::{Deserialize, Serialize};
+
+use crate::autopilot::Autopilot;
+use crate::cli::Options;
+use crate::{Bird, Flapped, Game, Player};
+
+const FILE: &str = "stats.ron";
+const TOAST_SECS: f32 = 3.0;
+/// Seconds at the end of a toast spent fading out.
+const TOAST_FADE_SECS: f32 = 0.5;
+
+/// Lifetime numbers of the first player's own runs. Runs flown by the
+/// autopilot or played back from a replay do not count.
+#[derive(Resource, Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Stats {
+    pub runs: u32,
+    pub flaps: u64,
+    pub pipes: u64,
+    pub deaths: BTreeMap<Cause, u32>,
+    pub play_secs: f32,
+    pub longest_run_secs: f32,
+    /// Names of the achievements unlocked so far.
+    pub achievements: BTreeSet<String>,
+}
+
+impl Versioned for Stats {
+    const VERSION: u32 = 1;
+
+    fn migrate(version: u32, _text: &str) -> Result<Self, String> {
+        Err(format!("no stats schema {version}"))
+    }
+}
+
+impl Stats {
+    pub fn load() -> Self {
+        let Some(storage) = save::platform_storage(Folder::Data) else {
+            return Stats::default();
+        };
+        let loaded = save::load(storage.as_ref(), FILE);
+        for problem in &loaded.problems {
+            warn!("skipping unreadable stats: {problem}");
+        }
+        loaded.data.unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        let Some(mut storage) = save::platform_storage(Folder::Data) else {
+            return;
+        };
+        if let Err(err) = save::save(storage.as_mut(), FILE, self) {
+            warn!("could not save stats: {err}");
+        }
+    }
+
+    pub fn average_score(&self) -> f32 {
+        if self.runs == 0 {
+            0.0
+        } else {
+            self.pipes as f32 / self.runs as f32
+        }
+    }
+
+    fn deaths_by(&self, cause: Cause) -> u32 {
+        self.deaths.get(&cause).copied().unwrap_or(0)
+    }
+}
+
+/// How the run that just ended went.
+pub struct Run {
+    pub pipes: u32,
+    pub secs: f32,
+}
+
+/// What unlocks an achievement, checked whenever a run ends.
+pub enum Condition {
+    /// Pass this many pipes in one run.
+    RunPipes(u32),
+    /// Stay up this many seconds in one run.
+    RunSecs(f32),
+    /// Finish this many runs.
+    Runs(u32),
+    Flaps(u64),
+    /// Pass this many pipes over all runs.
+    Pipes(u64),
+    /// Come down this way this many times.
+    Deaths(Cause, u32),
+}
+
+impl Condition {
+    pub fn met(&self, stats: &Stats, run: &Run) -> bool {
+        match *self {
+            Condition::RunPipes(pipes) => run.pipes >= pipes,
+            Condition::RunSecs(secs) => run.secs >= secs,
+            Condition::Runs(runs) => stats.runs >= runs,
+            Condition::Flaps(flaps) => stats.flaps >= flaps,
+            Condition::Pipes(pipes) => stats.pipes >= pipes,
+            Condition::Deaths(cause, count) => stats.deaths_by(cause) >= count,
+        }
+    }
+}
+
+pub struct Achievement {
+    pub name: &'static str,
+    pub title: &'static str,
+    pub description: &'static str,
+    pub condition: Condition,
+}
+
+#[derive(Resource)]
+pub struct Achievements {
+    pub list: Vec<Achievement>,
+}
+
+impl Default for Achievements {
+    fn default() -> Self {
+        let achievement = |name, title, description, condition| Achievement {
+            name,
+            title,
+            description,
+            condition,
+        };
+        Achievements {
+            list: vec![
+                achievement(
+                    "first_flight",
+                    "First flight",
+                    "Finish a run",
+                    Condition::Runs(1),
+                ),
+                achievement(
+                    "ten_pipes",
+                    "Getting the hang of it",
+                    "Pass 10 pipes in one run",
+                    Condition::RunPipes(10),
+                ),
+                achievement(
+                    "fifty_pipes",
+                    "Half a hundred",
+                    "Pass 50 pipes in one run",
+                    Condition::RunPipes(50),
+                ),
+                achievement(
+                    "hundred_pipes",
+                    "Century",
+                    "Pass 100 pipes in one run",
+                    Condition::RunPipes(100),
+                ),
+                achievement(
+                    "marathon",
+                    "Marathon",
+                    "Stay up for two minutes in one run",
+                    Condition::RunSecs(120.0),
+                ),
+                achievement(
+                    "flapper",
+                    "Sore wings",
+                    "Flap 1000 times",
+                    Condition::Flaps(1000),
+                ),
+                achievement(
+                    "pipe_cleaner",
+                    "Pipe cleaner",
+                    "Pass 1000 pipes in all",
+                    Condition::Pipes(1000),
+                ),
+                achievement(
+                    "headbanger",
+                    "Headbanger",
+                    "Die to the ceiling 10 times",
+                    Condition::Deaths(Cause::Ceiling, 10),
+                ),
+                achievement(
+                    "gravity",
+                    "Gravity wins",
+                    "Fall to the ground 25 times",
+                    Condition::Deaths(Cause::Ground, 25),
+                ),
+            ],
+        }
+    }
+}
+
+/// Messages shown one after another in the corner, e.g. for achievements.
+#[derive(Resource, Default)]
+pub struct Toasts {
+    queue: VecDeque<String>,
+    showing: Option<Timer>,
+}
+
+impl Toasts {
+    pub fn push(&mut self, message: impl Into<String>) {
+        self.queue.push_back(message.into());
+    }
+}
+
+#[derive(Component)]
+struct ToastText;
+
+/// F4 shows the stats and achievements on the title screen or after a run.
+#[derive(Resource, Default)]
+struct StatsPanel {
+    open: bool,
+}
+
+#[derive(Component)]
+struct StatsPanelRoot;
+
+#[derive(Component)]
+struct StatsPanelText;
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Stats::load())
+            .init_resource::<Achievements>()
+            .init_resource::<Toasts>()
+            .init_resource::<StatsPanel>()
+            .add_startup_system(spawn_toast_text)
+            .add_startup_system(spawn_stats_panel)
+            .add_system(count_flaps)
+            .add_system(finish_run.after(count_flaps))
+            .add_system(show_toasts.after(finish_run))
+            .add_system(toggle_stats_panel)
+            .add_system(update_stats_panel.after(toggle_stats_panel));
+    }
+}
+
+/// Whether what the first player's bird does now is the player's own doing.
+fn counts(autopilot: &Autopilot, options: &Options) -> bool {
+    !autopilot.flying() && options.playback.is_none()
+}
+
+fn count_flaps(
+    mut flapped: EventReader<Flapped>,
+    mut stats: ResMut<Stats>,
+    autopilot: Res<Autopilot>,
+    options: Res<Options>,
+) {
+    let flaps = flapped.iter().filter(|event| event.player == 0).count();
+    if flaps > 0 && counts(&autopilot, &options) {
+        stats.flaps += flaps as u64;
+    }
+}
+
+/// Adds up the first player's run once their bird is down and unlocks any
+/// achievements it earned.
+fn finish_run(
+    mut stats: ResMut<Stats>,
+    achievements: Res<Achievements>,
+    mut toasts: ResMut<Toasts>,
+    autopilot: Res<Autopilot>,
+    options: Res<Options>,
+    players: Query<(&Bird, &Player)>,
+    mut counted: Local<bool>,
+) {
+    let Some((bird, _)) = players.iter().find(|(_, player)| player.index == 0) else {
+        return;
+    };
+    let Some(down_at) = bird.down_at else {
+        *counted = false;
+        return;
+    };
+    if std::mem::replace(&mut *counted, true) || !counts(&autopilot, &options) {
+        return;
+    }
+    let run = Run {
+        pipes: bird.score.max(0) as u32,
+        secs: down_at as f32 * TICK_SECS,
+    };
+    stats.runs += 1;
+    stats.pipes += run.pipes as u64;
+    stats.play_secs += run.secs;
+    stats.longest_run_secs = stats.longest_run_secs.max(run.secs);
+    if let Some(cause) = bird.cause {
+        *stats.deaths.entry(cause).or_default() += 1;
+    }
+    for achievement in &achievements.list {
+        if stats.achievements.contains(achievement.name) || !achievement.condition.met(&stats, &run)
+        {
+            continue;
+        }
+        info!("achievement unlocked: {}", achievement.title);
+        stats.achievements.insert(achievement.name.to_string());
+        toasts.push(format!(
+            "Achievement unlocked: {}\n{}",
+            achievement.title, achievement.description
+        ));
+    }
+    stats.save();
+}
+
+fn spawn_toast_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 26.0,
+                color: Color::GOLD,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.0),
+                bottom: Val::Px(50.0),
+                ..default()
+            },
+            ..default()
+        }),
+        ToastText,
+    ));
+}
+
+fn show_toasts(
+    time: Res<Time>,
+    mut toasts: ResMut<Toasts>,
+    mut text: Query<&mut Text, With<ToastText>>,
+) {
+    let Ok(mut text) = text.get_single_mut() else {
+        return;
+    };
+    if let Some(timer) = &mut toasts.showing {
+        timer.tick(time.delta());
+        let left = timer.duration().as_secs_f32() - timer.elapsed_secs();
+        let alpha = (left / TOAST_FADE_SECS).min(1.0);
+        text.sections[0].style.color.set_a(alpha);
+        if !timer.finished() {
+            return;
+        }
+        toasts.showing = None;
+        text.sections[0].value.clear();
+    }
+    if let Some(message) = toasts.queue.pop_front() {
+        text.sections[0].value = message;
+        text.sections[0].style.color.set_a(1.0);
+        toasts.showing = Some(Timer::from_seconds(TOAST_SECS, TimerMode::Once));
+    }
+}
+
+fn spawn_stats_panel(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::width(Val::Percent(100.0)),
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        top: Val::Px(0.0),
+                        bottom: Val::Px(0.0),
+                        ..default()
+                    },
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+                visibility: Visibility::Hidden,
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            StatsPanelRoot,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 18.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                StatsPanelText,
+            ));
+        });
+}
+
+fn toggle_stats_panel(
+    keyboard_input: Res<Input<KeyCode>>,
+    game: Res<Game>,
+    mut panel: ResMut<StatsPanel>,
+    mut roots: Query<&mut Visibility, With<StatsPanelRoot>>,
+) {
+    let open = if !matches!(game.state, 0 | 3) {
+        false
+    } else if keyboard_input.just_pressed(KeyCode::F4) {
+        !panel.open
+    } else {
+        panel.open
+    };
+    if open == panel.open {
+        return;
+    }
+    panel.open = open;
+    for mut visibility in &mut roots {
+        *visibility = if open {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+fn update_stats_panel(
+    panel: Res<StatsPanel>,
+    stats: Res<Stats>,
+    achievements: Res<Achievements>,
+    mut text: Query<&mut Text, With<StatsPanelText>>,
+) {
+    if !panel.open || !(panel.is_changed() || stats.is_changed()) {
+        return;
+    }
+    let minutes = |secs: f32| format!("{}:{:02}", secs as u32 / 60, secs as u32 % 60);
+    let mut value = format!(
+        "Stats\n\nRuns: {}\nFlaps: {}\nPipes passed: {}\nAverage score: {:.1}\n\
+         Longest run: {}\nPlay time: {}\n",
+        stats.runs,
+        stats.flaps,
+        stats.pipes,
+        stats.average_score(),
+        minutes(stats.longest_run_secs),
+        minutes(stats.play_secs),
+    );
+    value.push_str("\nDeaths\n");
+    for (cause, label) in [
+        (Cause::UpperPipe, "Upper pipe"),
+        (Cause::LowerPipe, "Lower pipe"),
+        (Cause::Ground, "Ground"),
+        (Cause::Ceiling, "Ceiling"),
+    ] {
+        value.push_str(&format!("{label}: {}\n", stats.deaths_by(cause)));
+    }
+    value.push_str(&format!(
+        "\nAchievements {}/{}\n",
+        stats.achievements.len(),
+        achievements.list.len()
+    ));
+    for achievement in &achievements.list {
+        let mark = if stats.achievements.contains(achievement.name) {
+            "[x]"
+        } else {
+            "[ ]"
+        };
+        value.push_str(&format!(
+            "{mark} {} - {}\n",
+            achievement.title, achievement.description
+        ));
+    }
+    value.push_str("\nF4 close");
+    for mut text in &mut text {
+        text.sections[0].value = value.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn average_score_is_pipes_per_run() {
+        let mut stats = Stats::default();
+        assert_eq!(stats.average_score(), 0.0);
+        stats.runs = 4;
+        stats.pipes = 10;
+        assert_eq!(stats.average_score(), 2.5);
+    }
+
+    #[test]
+    fn conditions_are_met_at_their_threshold() {
+        let stats = Stats {
+            runs: 10,
+            flaps: 500,
+            pipes: 100,
+            deaths: BTreeMap::from([(Cause::Ground, 3)]),
+            ..default()
+        };
+        let run = Run {
+            pipes: 25,
+            secs: 60.0,
+        };
+        let met = |condition: Condition| condition.met(&stats, &run);
+        assert!(met(Condition::RunPipes(25)));
+        assert!(!met(Condition::RunPipes(26)));
+        assert!(met(Condition::RunSecs(60.0)));
+        assert!(!met(Condition::RunSecs(60.5)));
+        assert!(met(Condition::Runs(10)));
+        assert!(!met(Condition::Runs(11)));
+        assert!(met(Condition::Flaps(500)));
+        assert!(!met(Condition::Flaps(501)));
+        assert!(met(Condition::Pipes(100)));
+        assert!(!met(Condition::Pipes(101)));
+        assert!(met(Condition::Deaths(Cause::Ground, 3)));
+        assert!(!met(Condition::Deaths(Cause::Ground, 4)));
+        assert!(!met(Condition::Deaths(Cause::Ceiling, 1)));
+    }
+}
@@ -0,0 +1,256 @@
+use bevy::{prelude::*, time::common_conditions::on_timer, utils::Duration};
+use chrono::Timelike;
+
+use crate::atlas::{Art, ArtLoader};
+use crate::parallax::Parallax;
+
+/// A named look for the whole game.
+pub struct Theme {
+    pub name: &'static str,
+    pub background: Art,
+    pub ground: Art,
+    pub pipe: Art,
+    pub sky: Color,
+    pub bird: Color,
+    pub text: Color,
+    /// Local hours `[start, end)` in which this theme is picked when rotating
+    /// by time of day; the range may wrap past midnight.
+    pub hours: Option<(u32, u32)>,
+}
+
+pub struct ThemeRotation {
+    pub time_of_day: bool,
+    pub every_score: Option<i32>,
+}
+
+#[derive(Resource)]
+pub struct Themes {
+    pub list: Vec<Theme>,
+    pub current: usize,
+    pub rotation: ThemeRotation,
+    pub fade_secs: f32,
+}
+
+impl Themes {
+    pub fn theme(&self) -> &Theme {
+        &self.list[self.current]
+    }
+}
+
+impl Default for Themes {
+    fn default() -> Self {
+        Themes {
+            list: vec![
+                Theme {
+                    name: "day",
+                    background: Art::Image("data/background.png"),
+                    ground: Art::Image("image/ground.png"),
+                    pipe: Art::Image("image/pipe2.png"),
+                    sky: Color::WHITE,
+                    bird: Color::WHITE,
+                    text: Color::MIDNIGHT_BLUE,
+                    hours: Some((7, 19)),
+                },
+                Theme {
+                    name: "classic",
+                    background: Art::Region("background"),
+                    ground: Art::Region("footer1"),
+                    pipe: Art::Region("pipe"),
+                    sky: Color::WHITE,
+                    bird: Color::WHITE,
+                    text: Color::WHITE,
+                    hours: None,
+                },
+                Theme {
+                    name: "night",
+                    background: Art::Region("background"),
+                    ground: Art::Region("footer2"),
+                    pipe: Art::Region("Layer-13"),
+                    sky: Color::rgb(0.35, 0.4, 0.65),
+                    bird: Color::rgb(0.85, 0.85, 1.0),
+                    text: Color::ANTIQUE_WHITE,
+                    hours: Some((19, 7)),
+                },
+            ],
+            current: 0,
+            rotation: ThemeRotation {
+                time_of_day: true,
+                every_score: None,
+            },
+            fade_secs: 1.0,
+        }
+    }
+}
+
+/// Marks an entity whose look comes from the current theme.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Themed {
+    Background,
+    Ground,
+    Pipe,
+    Bird,
+    Text,
+}
+
+/// Colour a themed sprite keeps on top of the theme, e.g. a bird skin's tint.
+#[derive(Component, Clone, Copy)]
+pub struct BaseTint(pub Color);
+
+/// Colours of the theme being faded out while a switch is in progress.
+#[derive(Resource)]
+struct ThemeTransition {
+    from: usize,
+    timer: Timer,
+    swapped: bool,
+}
+
+pub struct ThemePlugin;
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Themes>()
+            .add_startup_system(rotate_by_time_of_day)
+            .add_system(rotate_by_time_of_day.run_if(on_timer(Duration::from_secs(60))))
+            .add_system(cycle_theme)
+            .add_system(start_transition.after(cycle_theme))
+            .add_system(crossfade.after(start_transition));
+    }
+}
+
+fn rotate_by_time_of_day(mut themes: ResMut<Themes>) {
+    if themes.rotation.time_of_day {
+        pick_by_time_of_day(&mut themes);
+    }
+}
+
+/// Switches to the theme whose hours cover the local time, if any.
+pub fn pick_by_time_of_day(themes: &mut Themes) {
+    let hour = chrono::Local::now().hour();
+    let in_hours = |(start, end): (u32, u32)| {
+        if start <= end {
+            (start..end).contains(&hour)
+        } else {
+            hour >= start || hour < end
+        }
+    };
+    if let Some(index) = themes
+        .list
+        .iter()
+        .position(|theme| theme.hours.is_some_and(in_hours))
+    {
+        themes.current = index;
+    }
+}
+
+/// Score milestones move on to the next theme in the list.
+pub fn rotate_by_score(score: i32, themes: &mut Themes) {
+    if let Some(step) = themes.rotation.every_score.filter(|step| *step > 0) {
+        let index = (score / step) as usize % themes.list.len();
+        themes.current = index;
+    }
+}
+
+fn cycle_theme(keyboard_input: Res<Input<KeyCode>>, mut themes: ResMut<Themes>) {
+    if keyboard_input.just_pressed(KeyCode::F7) {
+        let index = (themes.current + 1) % themes.list.len();
+        themes.current = index;
+        info!("theme: {}", themes.theme().name);
+    }
+}
+
+fn start_transition(
+    mut commands: Commands,
+    themes: Res<Themes>,
+    mut parallax: ResMut<Parallax>,
+    mut last: Local<Option<usize>>,
+) {
+    if *last == Some(themes.current) {
+        return;
+    }
+    let theme = themes.theme();
+    for layer in parallax.layers.iter_mut() {
+        match layer.themed {
+            Some(Themed::Background) => {
+                layer.art = theme.background;
+                layer.color = theme.sky;
+            }
+            Some(Themed::Ground) => layer.art = theme.ground,
+            _ => {}
+        }
+    }
+    if let Some(from) = *last {
+        commands.insert_resource(ThemeTransition {
+            from,
+            timer: Timer::from_seconds(themes.fade_secs, TimerMode::Once),
+            swapped: false,
+        });
+    }
+    *last = Some(themes.current);
+}
+
+fn mix(from: Color, to: Color, t: f32) -> Color {
+    let [r0, g0, b0, a0] = from.as_rgba_f32();
+    let [r1, g1, b1, a1] = to.as_rgba_f32();
+    Color::rgba(
+        r0 + (r1 - r0) * t,
+        g0 + (g1 - g0) * t,
+        b0 + (b1 - b0) * t,
+        a0 + (a1 - a0) * t,
+    )
+}
+
+/// Scenery crossfades inside the parallax layers. Pipes dip out and come back
+/// with the new art, while bird and text colours blend over the same time.
+fn crossfade(
+    mut commands: Commands,
+    time: Res<Time>,
+    themes: Res<Themes>,
+    transition: Option<ResMut<ThemeTransition>>,
+    art: ArtLoader,
+    mut sprites: Query<(&Themed, &mut Sprite, &mut Handle<Image>)>,
+    mut birds: Query<(&Themed, &mut TextureAtlasSprite, Option<&BaseTint>)>,
+    mut texts: Query<(&Themed, &mut Text)>,
+) {
+    let Some(mut transition) = transition else {
+        return;
+    };
+    transition.timer.tick(time.delta());
+    let t = transition.timer.percent();
+    let from = &themes.list[transition.from];
+    let to = themes.theme();
+
+    let pipe = if t >= 0.5 && !transition.swapped {
+        let resolved = art.resolve(to.pipe);
+        transition.swapped = resolved.is_some();
+        resolved
+    } else {
+        None
+    };
+    for (themed, mut sprite, mut texture) in &mut sprites {
+        if *themed != Themed::Pipe {
+            continue;
+        }
+        if let Some(pipe) = &pipe {
+            *texture = pipe.texture.clone();
+            sprite.rect = pipe.rect;
+        }
+        sprite.color.set_a((1.0 - 2.0 * t).abs());
+    }
+    for (themed, mut sprite, tint) in &mut birds {
+        if *themed == Themed::Bird {
+            let tint = tint.map_or(Color::WHITE, |tint| tint.0);
+            sprite.color = mix(from.bird, to.bird, t) * tint.as_rgba_f32();
+        }
+    }
+    for (themed, mut text) in &mut texts {
+        if *themed == Themed::Text {
+            for section in text.sections.iter_mut() {
+                section.style.color = mix(from.text, to.text, t);
+            }
+        }
+    }
+
+    if transition.timer.finished() && transition.swapped {
+        commands.remove_resource::<ThemeTransition>();
+    }
+}
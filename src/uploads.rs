@@ -0,0 +1,188 @@
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use bevy::prelude::*;
+use my_bevy_game::save::{self, Folder};
+use my_bevy_game::scoreboard::{Delivery, LeaderboardBackend, ScoreEntry, ScoreQueue, Submission};
+
+use crate::cli::Options;
+use crate::ghost::RunRecorded;
+use crate::stats::Toasts;
+
+const QUEUE_FILE: &str = "score-queue.ron";
+/// How long scores wait before the server is tried again.
+const RETRY: Duration = Duration::from_secs(30);
+/// Online scores shown on the leaderboard screen.
+const TOP: usize = 10;
+
+enum Report {
+    Rejected(Submission, String),
+    /// How many scores are still waiting.
+    Waiting(usize),
+    Top(Vec<ScoreEntry>),
+}
+
+/// Sends the player's own runs to the online leaderboard, when the game was
+/// started with `--scoreboard [url]`. The server is only ever talked to on
+/// its own thread, which keeps scores that cannot be delivered in a saved
+/// queue and tries them again later.
+#[derive(Resource)]
+pub struct Uploads {
+    submissions: mpsc::Sender<Submission>,
+    reports: Arc<Mutex<Vec<Report>>>,
+    /// Scores not delivered yet.
+    pub waiting: usize,
+    /// The best scores online, once the server has answered.
+    pub top: Vec<ScoreEntry>,
+}
+
+pub struct UploadsPlugin;
+
+impl Plugin for UploadsPlugin {
+    fn build(&self, app: &mut App) {
+        let Some(url) = app.world.resource::<Options>().scoreboard.clone() else {
+            return;
+        };
+        #[cfg(target_arch = "wasm32")]
+        {
+            warn!("the online leaderboard at {url} is not available in the browser");
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            info!("sending scores to {url}");
+            let backend = my_bevy_game::scoreboard::HttpBackend::new(&url);
+            let (submissions, inbox) = mpsc::channel();
+            let reports = Arc::new(Mutex::new(Vec::new()));
+            let outbox = reports.clone();
+            std::thread::spawn(move || deliver(backend, inbox, outbox));
+            app.insert_resource(Uploads {
+                submissions,
+                reports,
+                waiting: 0,
+                top: Vec::new(),
+            })
+            .add_system(submit_runs)
+            .add_system(read_reports);
+        }
+    }
+}
+
+fn save_queue(queue: &ScoreQueue) {
+    let Some(mut storage) = save::platform_storage(Folder::Data) else {
+        return;
+    };
+    if let Err(err) = save::save(storage.as_mut(), QUEUE_FILE, queue) {
+        warn!("could not save the score queue: {err}");
+    }
+}
+
+/// Runs on its own thread until the game goes away: delivers what is queued,
+/// then waits for a new score or the next retry.
+fn deliver(
+    mut backend: impl LeaderboardBackend,
+    inbox: mpsc::Receiver<Submission>,
+    outbox: Arc<Mutex<Vec<Report>>>,
+) {
+    let mut queue: ScoreQueue = save::platform_storage(Folder::Data)
+        .and_then(|storage| save::load(storage.as_ref(), QUEUE_FILE).data)
+        .unwrap_or_default();
+    let mut fetch_top = true;
+    loop {
+        let mut reports = Vec::new();
+        let mut changed = false;
+        for delivery in queue.flush(&mut backend) {
+            match delivery {
+                Delivery::Accepted(submission) => {
+                    info!("score {} is online", submission.replay.score);
+                    changed = true;
+                    fetch_top = true;
+                }
+                Delivery::Rejected(submission, reason) => {
+                    warn!("score {} turned down: {reason}", submission.replay.score);
+                    changed = true;
+                    reports.push(Report::Rejected(submission, reason));
+                }
+                Delivery::Held(reason) => {
+                    info!(
+                        "{} scores wait for the server: {reason}",
+                        queue.pending.len()
+                    )
+                }
+            }
+        }
+        if changed {
+            save_queue(&queue);
+        }
+        reports.push(Report::Waiting(queue.pending.len()));
+        if fetch_top {
+            if let Ok(top) = backend.top(TOP) {
+                reports.push(Report::Top(top));
+                fetch_top = false;
+            }
+        }
+        outbox.lock().unwrap().extend(reports);
+        match inbox.recv_timeout(RETRY) {
+            Ok(submission) => {
+                queue.push(submission);
+                save_queue(&queue);
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Sends the scores of runs the server can fly again. The initials for the
+/// local board are typed after the run ends, so the name comes from `--name`.
+fn submit_runs(
+    mut recorded: EventReader<RunRecorded>,
+    uploads: Res<Uploads>,
+    options: Res<Options>,
+    mut toasts: ResMut<Toasts>,
+) {
+    for RunRecorded { replay } in recorded.iter() {
+        if replay.score <= 0 {
+            continue;
+        }
+        if let Err(divergence) = replay.verify() {
+            warn!(
+                "not sending score {}, the server could not check it: {divergence}",
+                replay.score
+            );
+            toasts.push(format!(
+                "Score {} was not sent online,
+the replay does not hold up at {divergence}",
+                replay.score
+            ));
+            continue;
+        }
+        let submission = Submission {
+            name: options
+                .name
+                .clone()
+                .unwrap_or_else(|| "anonymous".to_string()),
+            replay: replay.clone(),
+        };
+        if uploads.submissions.send(submission).is_err() {
+            warn!("the score uploader stopped, score {} is lost", replay.score);
+        }
+    }
+}
+
+fn read_reports(mut uploads: ResMut<Uploads>, mut toasts: ResMut<Toasts>) {
+    let reports = std::mem::take(&mut *uploads.reports.lock().unwrap());
+    for report in reports {
+        match report {
+            Report::Rejected(submission, reason) => toasts.push(format!(
+                "Online leaderboard turned down {}:\n{reason}",
+                submission.replay.score
+            )),
+            Report::Waiting(waiting) => {
+                if uploads.waiting != waiting {
+                    uploads.waiting = waiting;
+                }
+            }
+            Report::Top(top) => uploads.top = top,
+        }
+    }
+}
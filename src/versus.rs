@@ -0,0 +1,136 @@
+use bevy::prelude::*;
+
+use crate::leaderboard::no_name_entry;
+use crate::online::Online;
+use crate::profile::Profile;
+use crate::settings::{menu_closed, Settings};
+use crate::skins::{Hitbox, Skinned, Skins};
+use crate::theme::Themed;
+use crate::{Bird, Collider, Game, ObjectTag, Player};
+
+/// Whether a second player races on this keyboard, each with their own flap
+/// key from the settings.
+#[derive(Resource, Default)]
+pub struct Versus {
+    pub on: bool,
+}
+
+#[derive(Component)]
+struct VersusText;
+
+pub struct VersusPlugin;
+
+impl Plugin for VersusPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Versus>()
+            .add_startup_system(spawn_versus_text)
+            .add_system(toggle_versus.run_if(menu_closed).run_if(no_name_entry))
+            .add_system(update_versus_text);
+    }
+}
+
+/// V adds or removes the second bird before the first flap.
+fn toggle_versus(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    game: Res<Game>,
+    mut versus: ResMut<Versus>,
+    skins: Res<Skins>,
+    profile: Res<Profile>,
+    settings: Res<Settings>,
+    players: Query<(Entity, &Player)>,
+    online: Option<Res<Online>>,
+) {
+    // The server only knows about one bird per game.
+    if online.is_some() || game.state != 0 || !keyboard_input.just_pressed(KeyCode::V) {
+        return;
+    }
+    versus.on = !versus.on;
+    if !versus.on {
+        for (entity, player) in &players {
+            if player.index > 0 {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        return;
+    }
+    let skin = skins.get(skins.cycle(&profile, 1));
+    commands.spawn((
+        SpriteSheetBundle {
+            transform: Transform::from_xyz(-60.0, 0.0, 0.0),
+            ..default()
+        },
+        Skinned::new(skin.name),
+        Hitbox(skin.hitbox),
+        Bird::default(),
+        Player {
+            index: 1,
+            key: settings.flap_key(1),
+        },
+        ObjectTag::Bird,
+        Collider,
+        Themed::Bird,
+    ));
+}
+
+fn spawn_versus_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 28.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_text_alignment(TextAlignment::Right)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                right: Val::Px(10.0),
+                top: Val::Px(120.0),
+                ..default()
+            },
+            ..default()
+        }),
+        VersusText,
+    ));
+}
+
+/// Both scores while racing, and who won once both birds are down.
+fn update_versus_text(
+    game: Res<Game>,
+    versus: Res<Versus>,
+    players: Query<(&Bird, &Player)>,
+    mut text: Query<&mut Text, With<VersusText>>,
+) {
+    let mut players: Vec<_> = players.iter().collect();
+    players.sort_by_key(|(_, player)| player.index);
+    let value = match players.as_slice() {
+        [(one, one_player), (two, two_player)] if versus.on => {
+            let mut value = format!(
+                "P1 ({:?}) {}\nP2 ({:?}) {}",
+                one_player.key, one.score, two_player.key, two.score
+            );
+            if game.state == 3 {
+                // A tie on pipes goes to whoever stayed up longer.
+                let winner = one
+                    .score
+                    .cmp(&two.score)
+                    .then(one.down_at.cmp(&two.down_at));
+                value.push_str(match winner {
+                    std::cmp::Ordering::Greater => "\nPlayer 1 wins!",
+                    std::cmp::Ordering::Less => "\nPlayer 2 wins!",
+                    std::cmp::Ordering::Equal => "\nDraw!",
+                });
+            }
+            value
+        }
+        _ => String::new(),
+    };
+    for mut text in &mut text {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}